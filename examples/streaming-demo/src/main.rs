@@ -1,4 +1,4 @@
-use foton::{Foton, Req, Res, StreamSender};
+use foton::{Event, Foton, IntoRes, LastEventId, Req, Res, Sse, SseSender, StreamSender};
 use tokio::time::{Duration, sleep};
 
 async fn index(_req: Req) -> Res {
@@ -48,27 +48,25 @@ async fn countdown_handler(_req: Req) -> Res {
     })
 }
 
-async fn sse_handler(_req: Req) -> Res {
-    let mut res = Res::stream(|mut tx: StreamSender| async move {
-        for i in 1..=20 {
-            let event = format!(
-                "data: {{\"count\": {}, \"timestamp\": {}}}\n\n",
+async fn sse_handler(LastEventId(last): LastEventId) -> Res {
+    // Resume counting after the last event a reconnecting client received
+    let start = last.and_then(|id| id.parse::<u32>().ok()).unwrap_or(0) + 1;
+
+    Sse::new(move |tx: SseSender| async move {
+        for i in start..=20 {
+            let event = Event::default().id(i.to_string()).data(format!(
+                "{{\"count\": {}, \"timestamp\": {}}}",
                 i,
                 i * 1000
-            );
-            tx.send_text(event).await.ok();
+            ));
+            if tx.send(event).await.is_err() {
+                break;
+            }
             sleep(Duration::from_millis(1000)).await;
         }
-    });
-
-    res.headers_mut()
-        .insert("content-type", "text/event-stream".parse().unwrap());
-    res.headers_mut()
-        .insert("cache-control", "no-cache".parse().unwrap());
-    res.headers_mut()
-        .insert("connection", "keep-alive".parse().unwrap());
-
-    res
+    })
+    .keep_alive(Duration::from_secs(15))
+    .into_res()
 }
 
 #[tokio::main]
@@ -2,24 +2,36 @@
 
 use std::collections::HashMap;
 use std::convert::Infallible;
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use crate::connect_info::{Cidr, ConnectInfo};
+use crate::file::Preconditions;
+use crate::middleware::NextFn;
+use crate::proxy_protocol;
+use crate::request_id;
 use crate::res::BoxBody;
-use hyper::body::Incoming;
-use hyper::server::conn::{http1, http2};
+use crate::route::ANY;
+use crate::router::FlatFallback;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Body;
 use hyper::service::service_fn;
 use hyper::{Method, Request, Response};
-use hyper_util::rt::TokioIo;
-use tokio::net::TcpListener;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::signal;
 use tokio::sync::watch;
+use tokio::time::Instant;
 
+use crate::error_handler;
 use crate::{
-    Error, ErrorHandler, Handler, IntoRes, Middleware, Req, Res, Result, Router, ServerConfig,
-    handler::IntoHandler,
+    Error, ErrorHandler, Handler, IntoRes, Middleware, Req, Result, RouteError, RouteIssue, Router,
+    ServerConfig, handler::IntoHandler,
 };
 
 type BoxedHandler<S> = Arc<dyn Handler<S>>;
@@ -28,21 +40,31 @@ type SharedMiddlewares<S> = Arc<Vec<BoxedMiddleware<S>>>;
 type BoxedErrorHandler = Arc<dyn ErrorHandler>;
 type MethodHandlers<S> = HashMap<Method, (BoxedHandler<S>, SharedMiddlewares<S>)>;
 
+/// Time allowed for a PROXY protocol header when no request timeout is set.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// HTTP application.
 pub struct Foton<S = ()> {
     routes: Vec<(Method, String, BoxedHandler<S>, SharedMiddlewares<S>)>,
+    fallback: Option<BoxedHandler<S>>,
+    fallbacks: Vec<FlatFallback<S>>,
+    fallback_routes: Vec<FlatFallback<S>>,
     middlewares: Vec<BoxedMiddleware<S>>,
+    global_middlewares: SharedMiddlewares<S>,
     state: Option<Arc<S>>,
     router: Option<matchit::Router<Arc<MethodHandlers<S>>>>,
     error_handler: Option<BoxedErrorHandler>,
 
     // Configuration
     body_limit: Option<usize>,
+    path_error_status: u16,
     request_timeout: Option<Duration>,
     handler_timeout: Option<Duration>,
     http2_enabled: bool,
     max_connections: Option<usize>,
     keep_alive: Option<Duration>,
+    trusted_proxies: Arc<[Cidr]>,
+    proxy_protocol: bool,
 }
 
 impl Foton<()> {
@@ -50,16 +72,23 @@ impl Foton<()> {
     pub fn new() -> Self {
         Self {
             routes: Vec::new(),
+            fallback: None,
+            fallbacks: Vec::new(),
+            fallback_routes: Vec::new(),
             middlewares: Vec::new(),
+            global_middlewares: Arc::new(Vec::new()),
             state: Some(Arc::new(())),
             router: None,
             error_handler: None,
             body_limit: None,
+            path_error_status: 400,
             request_timeout: None,
             handler_timeout: None,
             http2_enabled: false,
             max_connections: None,
             keep_alive: None,
+            trusted_proxies: Arc::new([]),
+            proxy_protocol: false,
         }
     }
 }
@@ -71,20 +100,30 @@ impl<S: Send + Sync + 'static> Foton<S> {
     pub fn with_state(state: S) -> Self {
         Self {
             routes: Vec::new(),
+            fallback: None,
+            fallbacks: Vec::new(),
+            fallback_routes: Vec::new(),
             middlewares: Vec::new(),
+            global_middlewares: Arc::new(Vec::new()),
             state: Some(Arc::new(state)),
             router: None,
             error_handler: None,
             body_limit: None,
+            path_error_status: 400,
             request_timeout: None,
             handler_timeout: None,
             http2_enabled: false,
             max_connections: None,
             keep_alive: None,
+            trusted_proxies: Arc::new([]),
+            proxy_protocol: false,
         }
     }
 
     /// Set custom error handler.
+    ///
+    /// Every error response is rendered through it, including extractor
+    /// failures, 404/405 routing errors, handler timeouts and `Err` returns.
     pub fn set_error_handler<H: ErrorHandler>(&mut self, handler: H) {
         self.error_handler = Some(Arc::new(handler));
     }
@@ -101,12 +140,7 @@ impl<S: Send + Sync + 'static> Foton<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes.push((
-            Method::GET,
-            path.to_string(),
-            handler.into_handler(),
-            Arc::new(Vec::new()),
-        ));
+        self.on(Method::GET, path, handler);
     }
 
     /// Register a POST route.
@@ -114,12 +148,7 @@ impl<S: Send + Sync + 'static> Foton<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes.push((
-            Method::POST,
-            path.to_string(),
-            handler.into_handler(),
-            Arc::new(Vec::new()),
-        ));
+        self.on(Method::POST, path, handler);
     }
 
     /// Register a PUT route.
@@ -127,12 +156,7 @@ impl<S: Send + Sync + 'static> Foton<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes.push((
-            Method::PUT,
-            path.to_string(),
-            handler.into_handler(),
-            Arc::new(Vec::new()),
-        ));
+        self.on(Method::PUT, path, handler);
     }
 
     /// Register a DELETE route.
@@ -140,39 +164,123 @@ impl<S: Send + Sync + 'static> Foton<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes.push((
-            Method::DELETE,
-            path.to_string(),
-            handler.into_handler(),
-            Arc::new(Vec::new()),
-        ));
+        self.on(Method::DELETE, path, handler);
     }
 
     /// Register a PATCH route.
     pub fn patch<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::PATCH, path, handler);
+    }
+
+    /// Register a HEAD route.
+    pub fn head<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::HEAD, path, handler);
+    }
+
+    /// Register an OPTIONS route.
+    pub fn options<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::OPTIONS, path, handler);
+    }
+
+    /// Register a TRACE route.
+    pub fn trace<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::TRACE, path, handler);
+    }
+
+    /// Register a CONNECT route.
+    pub fn connect<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::CONNECT, path, handler);
+    }
+
+    /// Register a route matching any method without a more specific route.
+    pub fn any<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(ANY.clone(), path, handler);
+    }
+
+    /// Register a route for `method`, including extension methods.
+    ///
+    /// GET routes also answer HEAD requests unless a HEAD route is registered.
+    pub fn on<H, T>(&mut self, method: Method, path: &str, handler: H)
     where
         H: IntoHandler<S, T>,
     {
         self.routes.push((
-            Method::PATCH,
+            method,
             path.to_string(),
             handler.into_handler(),
             Arc::new(Vec::new()),
         ));
     }
 
+    /// Register one handler for each of `methods`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `methods` is empty.
+    pub fn methods<H, T>(&mut self, methods: &[Method], path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        assert!(!methods.is_empty(), "route methods must not be empty");
+        let handler = handler.into_handler();
+        for method in methods {
+            self.routes.push((
+                method.clone(),
+                path.to_string(),
+                Arc::clone(&handler),
+                Arc::new(Vec::new()),
+            ));
+        }
+    }
+
     /// Register a route with per-route middleware.
     pub fn route(&mut self, route: crate::Route<S>) {
-        self.routes
-            .push((route.method, route.path, route.handler, route.middlewares));
+        for method in route.methods {
+            self.routes.push((
+                method,
+                route.path.clone(),
+                Arc::clone(&route.handler),
+                Arc::clone(&route.middlewares),
+            ));
+        }
+    }
+
+    /// Handle requests that match no route, instead of the default 404.
+    ///
+    /// Global middleware runs for fallback responses. Nested routers can
+    /// claim their own prefix with [`Router::fallback`].
+    pub fn fallback<H, T>(&mut self, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.fallback = Some(handler.into_handler());
     }
 
     /// Mount a router at a prefix.
     pub fn nest(&mut self, prefix: &str, router: Router<S>) {
-        let flattened = router.flatten(prefix);
+        let (flattened, fallbacks) = router.flatten(prefix);
         for (method, path, handler, middlewares) in flattened {
             self.routes.push((method, path, handler, middlewares));
         }
+        self.fallbacks.extend(fallbacks);
     }
 
     /// Get the number of registered routes.
@@ -190,7 +298,18 @@ impl<S: Send + Sync + 'static> Foton<S> {
         self.body_limit = Some(limit);
     }
 
+    /// Set the status returned when a [`Path`](crate::Path) extractor fails.
+    ///
+    /// Defaults to 400 Bad Request; use 404 to treat unparsable segments as
+    /// missing resources.
+    pub fn set_path_error_status(&mut self, status: u16) {
+        self.path_error_status = status;
+    }
+
     /// Set request timeout duration.
+    ///
+    /// Bounds reading HTTP/1 request headers and uploading the request body.
+    /// Slow bodies are rejected with 408 Request Timeout.
     pub fn set_request_timeout(&mut self, timeout: Duration) {
         self.request_timeout = Some(timeout);
     }
@@ -201,6 +320,12 @@ impl<S: Send + Sync + 'static> Foton<S> {
     }
 
     /// Enable or disable HTTP/2 support.
+    ///
+    /// When enabled, each connection is served as HTTP/1.1 or HTTP/2 depending
+    /// on what the client speaks: cleartext HTTP/2 with prior knowledge (h2c)
+    /// and HTTP/1.1 share the same port, and HTTP/1.1 upgrades such as
+    /// WebSocket keep working. The HTTP/1.1 `Upgrade: h2c` mechanism is not
+    /// supported; such requests are answered over HTTP/1.1.
     pub fn set_http2(&mut self, enabled: bool) {
         self.http2_enabled = enabled;
     }
@@ -210,11 +335,32 @@ impl<S: Send + Sync + 'static> Foton<S> {
         self.max_connections = Some(max);
     }
 
-    /// Set TCP keep-alive duration.
+    /// Set keep-alive duration.
+    ///
+    /// Enables TCP keep-alive probes on accepted sockets and closes connections
+    /// that stay idle between requests for longer than `duration`.
     pub fn set_keep_alive(&mut self, duration: Duration) {
         self.keep_alive = Some(duration);
     }
 
+    /// Trust forwarding headers from connections in these networks.
+    ///
+    /// Used to resolve [`ClientIp`](crate::ClientIp); by default no proxy is
+    /// trusted and forwarding headers are ignored.
+    pub fn set_trusted_proxies(&mut self, proxies: impl IntoIterator<Item = Cidr>) {
+        self.trusted_proxies = proxies.into_iter().collect();
+    }
+
+    /// Require a PROXY protocol v1 or v2 header on every connection.
+    ///
+    /// The reported source becomes the connection's
+    /// [`remote_addr`](crate::ConnectInfo::remote_addr). Connections without
+    /// a valid header are closed, so only enable this when the listener is
+    /// reachable through the proxy alone.
+    pub fn set_proxy_protocol(&mut self, enabled: bool) {
+        self.proxy_protocol = enabled;
+    }
+
     /// Apply configuration from a config struct.
     pub fn apply_config(&mut self, config: ServerConfig) {
         if let Some(limit) = config.body_limit {
@@ -233,46 +379,172 @@ impl<S: Send + Sync + 'static> Foton<S> {
         self.keep_alive = config.keep_alive;
     }
 
-    fn build_router(&mut self) {
-        let mut router = matchit::Router::new();
-        let mut path_methods: HashMap<String, MethodHandlers<S>> = HashMap::new();
-
+    /// Build the route table, reporting every conflicting, invalid or duplicate route.
+    ///
+    /// [`Foton::listen`] builds automatically; call this first to fail fast
+    /// or to inspect the [`RouteIssue`]s. Building again picks up routes
+    /// registered since.
+    pub fn build(&mut self) -> std::result::Result<(), RouteError> {
         let global_middlewares = Arc::new(self.middlewares.clone());
+        let mut issues = Vec::new();
+
+        // Paths in registration order, so conflicts are reported deterministically
+        let mut paths: Vec<(String, MethodHandlers<S>)> = Vec::new();
+        let mut index: HashMap<&str, usize> = HashMap::new();
+
+        for (method, path, handler, route_middlewares) in &self.routes {
+            let combined_middlewares =
+                with_global(&global_middlewares, Arc::clone(route_middlewares));
+
+            let i = *index.entry(path).or_insert_with(|| {
+                paths.push((path.clone(), HashMap::new()));
+                paths.len() - 1
+            });
+            let previous = paths[i]
+                .1
+                .insert(method.clone(), (Arc::clone(handler), combined_middlewares));
+            if previous.is_some() {
+                issues.push(RouteIssue::Duplicate {
+                    method: method.clone(),
+                    path: path.clone(),
+                });
+            }
+        }
 
-        for (method, path, handler, route_middlewares) in self.routes.drain(..) {
-            let combined_middlewares: SharedMiddlewares<S> = if route_middlewares.is_empty() {
-                Arc::clone(&global_middlewares)
-            } else if global_middlewares.is_empty() {
-                route_middlewares
-            } else {
-                let mut combined =
-                    Vec::with_capacity(global_middlewares.len() + route_middlewares.len());
-                combined.extend_from_slice(&global_middlewares);
-                combined.extend_from_slice(&route_middlewares);
-                Arc::new(combined)
-            };
-
-            path_methods
-                .entry(path.clone())
-                .or_insert_with(HashMap::new)
-                .insert(method, (handler, combined_middlewares));
+        let mut router = matchit::Router::new();
+        for (path, methods) in paths {
+            if !path.starts_with('/') {
+                issues.push(RouteIssue::Invalid {
+                    path,
+                    reason: "Route paths must start with '/'".to_string(),
+                });
+                continue;
+            }
+            if let Err(error) = router.insert(&path, Arc::new(methods)) {
+                issues.push(match error {
+                    matchit::InsertError::Conflict { with } => RouteIssue::Conflict {
+                        path,
+                        existing: with,
+                    },
+                    other => RouteIssue::Invalid {
+                        path,
+                        reason: other.to_string(),
+                    },
+                });
+            }
         }
 
-        for (path, methods) in path_methods {
-            router.insert(&path, Arc::new(methods)).ok();
+        if !issues.is_empty() {
+            return Err(RouteError::new(issues));
         }
 
+        let root_fallback = self
+            .fallback
+            .iter()
+            .map(|handler| (String::new(), Arc::clone(handler), Arc::new(Vec::new())));
+        let mut fallback_routes: Vec<FlatFallback<S>> = self
+            .fallbacks
+            .iter()
+            .cloned()
+            .chain(root_fallback)
+            .map(|(prefix, handler, middlewares)| {
+                (
+                    prefix,
+                    handler,
+                    with_global(&global_middlewares, middlewares),
+                )
+            })
+            .collect();
+        // Innermost prefixes first
+        fallback_routes.sort_by_key(|(prefix, _, _)| std::cmp::Reverse(prefix.len()));
+
         self.router = Some(router);
+        self.fallback_routes = fallback_routes;
+        self.global_middlewares = global_middlewares;
+        Ok(())
+    }
+
+    /// Build the router and return an in-process test client.
+    ///
+    /// Requests are dispatched through the same pipeline as [`Foton::listen`]
+    /// without binding a socket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the routes fail to [`build`](Foton::build).
+    pub fn into_test_client(self) -> crate::test::TestClient<S> {
+        crate::test::TestClient::new(self)
     }
 
     /// Start the HTTP server.
     ///
     /// Implements graceful shutdown on SIGTERM/SIGINT signals.
     /// In-flight requests complete before the server terminates.
-    pub async fn listen(mut self, addr: impl Into<SocketAddr>) -> Result<()> {
-        let addr = addr.into();
-        self.build_router();
+    pub async fn listen(self, addr: impl Into<SocketAddr>) -> Result<()> {
+        self.run(addr.into(), |app, stream, connect_info, shutdown_rx| {
+            let http2 = app.http2_enabled;
+            app.serve_connection(TokioIo::new(stream), Some(connect_info), http2, shutdown_rx)
+        })
+        .await
+    }
+
+    /// Start the HTTPS server.
+    ///
+    /// HTTP/2 is negotiated via ALPN for clients that offer `h2`; others are
+    /// served over HTTP/1.1. Certificates can be rotated at runtime through
+    /// [`TlsConfig`](crate::TlsConfig) without restarting.
+    #[cfg(feature = "tls")]
+    pub async fn listen_tls(
+        self,
+        addr: impl Into<SocketAddr>,
+        tls: crate::tls::TlsConfig,
+    ) -> Result<()> {
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls.server_config()?));
+        let handshake_timeout = self.request_timeout;
+
+        self.run(
+            addr.into(),
+            move |app, stream, connect_info, shutdown_rx| {
+                let acceptor = acceptor.clone();
+                async move {
+                    let handshake = acceptor.accept(stream);
+                    let stream = match handshake_timeout {
+                        Some(timeout) => match tokio::time::timeout(timeout, handshake).await {
+                            Ok(result) => result,
+                            Err(_) => return,
+                        },
+                        None => handshake.await,
+                    };
+                    let Ok(stream) = stream else {
+                        return;
+                    };
+
+                    let http2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+                    app.serve_connection(
+                        TokioIo::new(stream),
+                        Some(connect_info),
+                        http2,
+                        shutdown_rx,
+                    )
+                    .await;
+                }
+            },
+        )
+        .await
+    }
+
+    /// Accept connections until shutdown is signalled, serving each with `serve`.
+    async fn run<F, Fut>(mut self, addr: SocketAddr, serve: F) -> Result<()>
+    where
+        F: Fn(Arc<Self>, TcpStream, ConnectInfo, watch::Receiver<bool>) -> Fut
+            + Send
+            + Sync
+            + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.build()?;
         let app = Arc::new(self);
+        let serve = Arc::new(serve);
         let listener = TcpListener::bind(addr).await?;
 
         let active_connections = Arc::new(AtomicUsize::new(0));
@@ -287,77 +559,54 @@ impl<S: Send + Sync + 'static> Foton<S> {
         loop {
             tokio::select! {
                 result = listener.accept() => {
-                    match result {
-                        Ok((stream, _)) => {
-                            // Check max connections limit
-                            if let Some(max) = app.max_connections {
-                                let current = active_connections.load(Ordering::Relaxed);
-                                if current >= max {
-                                    drop(stream);
-                                    continue;
-                                }
+                    if let Ok((mut stream, peer_addr)) = result {
+                        // Check max connections limit
+                        if let Some(max) = app.max_connections {
+                            let current = active_connections.load(Ordering::Relaxed);
+                            if current >= max {
+                                drop(stream);
+                                continue;
                             }
+                        }
 
-                            // Increment active connections
-                            active_connections.fetch_add(1, Ordering::Relaxed);
-
-                            let io = TokioIo::new(stream);
-                            let app = Arc::clone(&app);
-                            let mut shutdown_rx = shutdown_rx.clone();
-                            let active_connections = Arc::clone(&active_connections);
-                            let http2_enabled = app.http2_enabled;
-
-                            tokio::task::spawn(async move {
-                                if http2_enabled {
-                                    let conn = http2::Builder::new(hyper_util::rt::TokioExecutor::new())
-                                        .serve_connection(
-                                            io,
-                                            service_fn(move |req| {
-                                                let app = Arc::clone(&app);
-                                                async move { app.handle_request(req).await }
-                                            }),
-                                        );
-
-                                    let mut conn = std::pin::pin!(conn);
-
-                                    tokio::select! {
-                                        result = conn.as_mut() => {
-                                            let _ = result;
-                                        }
-                                        _ = shutdown_rx.changed() => {
-                                            conn.as_mut().graceful_shutdown();
-                                            let _ = conn.await;
-                                        }
+                        // Enable TCP keep-alive probes on the accepted socket
+                        if let Some(keep_alive) = app.keep_alive {
+                            let params = socket2::TcpKeepalive::new().with_time(keep_alive);
+                            let _ = socket2::SockRef::from(&stream).set_tcp_keepalive(&params);
+                        }
+
+                        // Increment active connections
+                        active_connections.fetch_add(1, Ordering::Relaxed);
+
+                        let app = Arc::clone(&app);
+                        let serve = Arc::clone(&serve);
+                        let shutdown_rx = shutdown_rx.clone();
+                        let active_connections = Arc::clone(&active_connections);
+
+                        tokio::task::spawn(async move {
+                            let local_addr = stream.local_addr().unwrap_or(addr);
+                            let mut connect_info = ConnectInfo::new(peer_addr, local_addr);
+                            if app.proxy_protocol {
+                                let timeout = app.request_timeout.unwrap_or(PROXY_HEADER_TIMEOUT);
+                                let header = proxy_protocol::read_header(&mut stream);
+                                match tokio::time::timeout(timeout, header).await {
+                                    Ok(Ok(Some(proxied))) => {
+                                        connect_info.remote_addr = proxied.source;
+                                        connect_info.local_addr = proxied.destination;
                                     }
-                                } else {
-                                    let conn = http1::Builder::new()
-                                        .serve_connection(
-                                            io,
-                                            service_fn(move |req| {
-                                                let app = Arc::clone(&app);
-                                                async move { app.handle_request(req).await }
-                                            }),
-                                        )
-                                        .with_upgrades();
-
-                                    let mut conn = std::pin::pin!(conn);
-
-                                    tokio::select! {
-                                        result = conn.as_mut() => {
-                                            let _ = result;
-                                        }
-                                        _ = shutdown_rx.changed() => {
-                                            conn.as_mut().graceful_shutdown();
-                                            let _ = conn.await;
-                                        }
+                                    Ok(Ok(None)) => {}
+                                    _ => {
+                                        active_connections.fetch_sub(1, Ordering::Relaxed);
+                                        return;
                                     }
                                 }
+                            }
 
-                                // Decrement active connections when done
-                                active_connections.fetch_sub(1, Ordering::Relaxed);
-                            });
-                        }
-                        Err(_) => {}
+                            serve(app, stream, connect_info, shutdown_rx).await;
+
+                            // Decrement active connections when done
+                            active_connections.fetch_sub(1, Ordering::Relaxed);
+                        });
                     }
                 }
                 _ = shutdown_rx.changed() => {
@@ -369,142 +618,173 @@ impl<S: Send + Sync + 'static> Foton<S> {
         Ok(())
     }
 
-    async fn handle_request(
+    /// Serve a single connection until it closes, idles out or shutdown is signalled.
+    ///
+    /// The protocol is detected from the connection preface; `http2_enabled`
+    /// set to `false` restricts the connection to HTTP/1.
+    async fn serve_connection<I>(
+        self: Arc<Self>,
+        io: I,
+        connect_info: Option<ConnectInfo>,
+        http2_enabled: bool,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) where
+        I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+    {
+        let activity = Arc::new(ConnActivity::new());
+        let keep_alive = self.keep_alive;
+        let request_timeout = self.request_timeout;
+
+        let service = {
+            let activity = Arc::clone(&activity);
+            service_fn(move |req| {
+                let app = Arc::clone(&self);
+                let activity = Arc::clone(&activity);
+                async move {
+                    // The request stays in flight until its body is fully sent
+                    let guard = ActivityGuard::begin(activity);
+                    let res = app.handle_request(req, connect_info).await;
+                    res.map(|res| res.map(|body| TrackedBody::new(body, guard)))
+                }
+            })
+        };
+
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .keep_alive(true)
+            .header_read_timeout(request_timeout);
+        builder.http2().timer(TokioTimer::new());
+        if let Some(interval) = keep_alive {
+            builder.http2().keep_alive_interval(interval);
+        }
+        if !http2_enabled {
+            builder = builder.http1_only();
+        }
+
+        let conn = builder.serve_connection_with_upgrades(io, service);
+        let mut conn = std::pin::pin!(conn);
+
+        tokio::select! {
+            result = conn.as_mut() => {
+                let _ = result;
+            }
+            _ = activity.idle(keep_alive) => {
+                conn.as_mut().graceful_shutdown();
+                let _ = conn.await;
+            }
+            _ = shutdown_rx.changed() => {
+                conn.as_mut().graceful_shutdown();
+                let _ = conn.await;
+            }
+        }
+    }
+
+    pub(crate) async fn handle_request<B>(
         &self,
-        req: Request<Incoming>,
-    ) -> std::result::Result<Response<BoxBody>, Infallible> {
+        req: Request<B>,
+        connect_info: Option<ConnectInfo>,
+    ) -> std::result::Result<Response<BoxBody>, Infallible>
+    where
+        B: Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<Error>,
+    {
         let path = req.uri().path().to_string();
         let method = req.method().clone();
         let mut rust_req = Req::from_hyper(req);
 
-        // Set body limit if configured
+        // Set body limit and upload deadline if configured
         rust_req.set_body_limit(self.body_limit);
+        rust_req.set_body_timeout(self.request_timeout);
+        rust_req.set_path_error_status(self.path_error_status);
+        rust_req.set_connect_info(connect_info);
+        rust_req.set_trusted_proxies(Arc::clone(&self.trusted_proxies));
+
+        if let Some(ref error_handler) = self.error_handler {
+            rust_req.extensions_mut().insert(Arc::clone(error_handler));
+        }
 
         // Extract upgrade future before rust_req is moved
         #[cfg(feature = "websocket")]
         let on_upgrade = rust_req.take_upgrade();
 
-        let response = match &self.router {
+        let state = match &self.state {
+            Some(s) => Arc::clone(s),
+            None => {
+                let response = Error::internal("State not initialized").into_res();
+                return Ok(
+                    error_handler::render(self.error_handler.as_ref(), response).into_hyper()
+                );
+            }
+        };
+
+        let (endpoint, middlewares) = match &self.router {
             Some(router) => match router.at(&path) {
                 Ok(matched) => {
-                    let mut params = HashMap::new();
-                    for (key, value) in matched.params.iter() {
-                        params.insert(key.to_string(), value.to_string());
-                    }
+                    let params = matched
+                        .params
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect();
                     rust_req.set_path_params(params);
 
-                    if let Some(ref error_handler) = self.error_handler {
-                        rust_req.extensions_mut().insert(Arc::clone(error_handler));
-                    }
-
                     let method_handlers = matched.value;
 
-                    match method_handlers.get(&method) {
+                    match find_handler(method_handlers, &method) {
                         Some((handler, middlewares)) => {
-                            let state = match &self.state {
-                                Some(s) => Arc::clone(s),
-                                None => {
-                                    return Ok(Error::internal("State not initialized")
-                                        .into_res()
-                                        .into_hyper());
-                                }
-                            };
-
-                            // Execute handler with optional timeout
-                            let handler_future = if middlewares.is_empty() {
-                                Box::pin(handler.call(rust_req, state))
-                            } else {
-                                let handler_clone = Arc::clone(handler);
-                                let mut next_fn: Arc<
-                                    dyn Fn(
-                                            Req,
-                                            Arc<S>,
-                                        )
-                                            -> std::pin::Pin<
-                                            Box<dyn std::future::Future<Output = Res> + Send>,
-                                        > + Send
-                                        + Sync,
-                                > = Arc::new(move |req, state| {
-                                    let handler = Arc::clone(&handler_clone);
-                                    Box::pin(async move { handler.call(req, state).await })
-                                });
-
-                                for middleware in middlewares.iter().rev() {
-                                    let middleware_clone = Arc::clone(middleware);
-                                    let inner = Arc::clone(&next_fn);
-                                    let state_for_middleware = Arc::clone(&state);
-
-                                    next_fn = Arc::new(move |req, _state| {
-                                        let mw = Arc::clone(&middleware_clone);
-                                        let inner_clone = Arc::clone(&inner);
-                                        let state_clone = Arc::clone(&state_for_middleware);
-
-                                        Box::pin(async move {
-                                            let next = crate::Next::new(
-                                                inner_clone,
-                                                Arc::clone(&state_clone),
-                                            );
-                                            mw.handle(req, state_clone, next).await
-                                        })
-                                    });
-                                }
-
-                                Box::pin(next_fn(rust_req, state))
-                            };
-
-                            // Apply handler timeout if configured
-                            if let Some(timeout) = self.handler_timeout {
-                                match tokio::time::timeout(timeout, handler_future).await {
-                                    Ok(res) => res,
-                                    Err(_) => {
-                                        use crate::IntoRes;
-                                        Error::Custom(format!(
-                                            "Handler timeout after {:?}",
-                                            timeout
-                                        ))
-                                        .into_res()
-                                    }
-                                }
-                            } else {
-                                handler_future.await
-                            }
+                            (self.endpoint(Arc::clone(handler)), Arc::clone(middlewares))
                         }
                         None => {
-                            use crate::IntoRes;
-                            let allowed_methods: Vec<String> = method_handlers
-                                .keys()
-                                .map(|m| m.as_str().to_string())
-                                .collect();
-
-                            let mut response = Error::method_not_allowed(&format!(
-                                "Method {} not allowed. Allowed methods: {}",
-                                method,
-                                allowed_methods.join(", ")
-                            ))
-                            .into_res();
-
-                            response
-                                .headers_mut()
-                                .insert("Allow", allowed_methods.join(", ").parse().unwrap());
-
-                            response
+                            // CORS preflights run the middleware of the method they ask about
+                            let requested = (method == Method::OPTIONS)
+                                .then(|| rust_req.header("access-control-request-method"))
+                                .flatten()
+                                .and_then(|m| m.parse::<Method>().ok());
+                            let middlewares = requested
+                                .and_then(|m| find_handler(method_handlers, &m))
+                                .map(|(_, middlewares)| Arc::clone(middlewares))
+                                .unwrap_or_else(|| Arc::clone(&self.global_middlewares));
+
+                            (
+                                self.method_not_allowed(&method, method_handlers),
+                                middlewares,
+                            )
                         }
                     }
                 }
                 Err(_) => {
-                    use crate::IntoRes;
-                    Error::not_found("Route not found").into_res()
+                    rust_req.extensions_mut().insert(UnmatchedPath);
+                    match find_fallback(&self.fallback_routes, &path) {
+                        Some((_, handler, middlewares)) => {
+                            (self.endpoint(Arc::clone(handler)), Arc::clone(middlewares))
+                        }
+                        None => (
+                            self.error_endpoint(|| Error::not_found("Route not found")),
+                            Arc::clone(&self.global_middlewares),
+                        ),
+                    }
                 }
             },
-            None => {
-                use crate::IntoRes;
-                Error::internal("Router not initialized").into_res()
-            }
+            None => (
+                self.error_endpoint(|| Error::internal("Router not initialized")),
+                Arc::new(Vec::new()),
+            ),
         };
 
+        let response = request_id::scope(async {
+            let response = self
+                .run_chain(rust_req, state, endpoint, &middlewares)
+                .await;
+
+            // Render routing, timeout and middleware errors
+            error_handler::render(self.error_handler.as_ref(), response)
+        })
+        .await;
+
         // Check for WebSocket upgrade
         #[cfg(feature = "websocket")]
-        {
+        let response = {
             let mut response_mut = response;
             if let Some(ws_callback) = response_mut.take_ws_callback() {
                 if let Some(upgrade_future) = on_upgrade {
@@ -521,12 +801,180 @@ impl<S: Send + Sync + 'static> Foton<S> {
                     });
                 }
             }
-            return Ok(response_mut.into_hyper());
+            response_mut
+        };
+
+        if method == Method::HEAD {
+            return Ok(strip_body(response.into_hyper()));
         }
 
-        #[cfg(not(feature = "websocket"))]
         Ok(response.into_hyper())
     }
+
+    /// Wrap a handler as the innermost step of a middleware chain.
+    ///
+    /// Handler errors are rendered before middleware sees the response.
+    fn endpoint(&self, handler: BoxedHandler<S>) -> NextFn<S> {
+        let handler_errors = self.error_handler.clone();
+        Arc::new(move |req, state| {
+            let handler = Arc::clone(&handler);
+            let handler_errors = handler_errors.clone();
+            Box::pin(async move {
+                let preconditions = Preconditions::from_req(&req);
+                let mut res = handler.call(req, state).await;
+                if let Some(preconditions) = preconditions {
+                    res = preconditions.apply(res).await;
+                }
+                error_handler::render(handler_errors.as_ref(), res)
+            })
+        })
+    }
+
+    /// Endpoint answering every request with a rendered error.
+    fn error_endpoint(&self, error: fn() -> Error) -> NextFn<S> {
+        let error_handler = self.error_handler.clone();
+        Arc::new(move |_req, _state| {
+            let res = error_handler::render(error_handler.as_ref(), error().into_res());
+            Box::pin(async move { res })
+        })
+    }
+
+    /// Endpoint answering 405 with an `Allow` header.
+    fn method_not_allowed(
+        &self,
+        method: &Method,
+        method_handlers: &MethodHandlers<S>,
+    ) -> NextFn<S> {
+        let mut allowed: Vec<&Method> = method_handlers.keys().collect();
+        if method_handlers.contains_key(&Method::GET)
+            && !method_handlers.contains_key(&Method::HEAD)
+        {
+            allowed.push(&Method::HEAD);
+        }
+        // Standard methods in their usual order, then extension methods by name
+        allowed.sort_by_key(|m| (method_rank(m), m.as_str()));
+        let allowed_methods: Vec<&str> = allowed.iter().map(|m| m.as_str()).collect();
+        let message = format!(
+            "Method {} not allowed. Allowed methods: {}",
+            method,
+            allowed_methods.join(", ")
+        );
+        let allow = allowed_methods.join(", ");
+        let error_handler = self.error_handler.clone();
+
+        Arc::new(move |_req, _state| {
+            let mut response = error_handler::render(
+                error_handler.as_ref(),
+                Error::method_not_allowed(message.clone()).into_res(),
+            );
+            if let Ok(allow) = allow.parse() {
+                response.headers_mut().insert("Allow", allow);
+            }
+            Box::pin(async move { response })
+        })
+    }
+
+    /// Run `endpoint` behind `middlewares`, applying the handler timeout.
+    async fn run_chain(
+        &self,
+        req: Req,
+        state: Arc<S>,
+        endpoint: NextFn<S>,
+        middlewares: &SharedMiddlewares<S>,
+    ) -> crate::Res {
+        let mut next_fn = endpoint;
+
+        for middleware in middlewares.iter().rev() {
+            let middleware_clone = Arc::clone(middleware);
+            let inner = Arc::clone(&next_fn);
+            let state_for_middleware = Arc::clone(&state);
+
+            next_fn = Arc::new(move |req, _state| {
+                let mw = Arc::clone(&middleware_clone);
+                let inner_clone = Arc::clone(&inner);
+                let state_clone = Arc::clone(&state_for_middleware);
+
+                Box::pin(async move {
+                    let next = crate::Next::new(inner_clone, Arc::clone(&state_clone));
+                    mw.handle(req, state_clone, next).await
+                })
+            });
+        }
+
+        let handler_future = next_fn(req, state);
+
+        // Apply handler timeout if configured
+        if let Some(timeout) = self.handler_timeout {
+            match tokio::time::timeout(timeout, handler_future).await {
+                Ok(res) => res,
+                Err(_) => Error::Custom(format!("Handler timeout after {:?}", timeout)).into_res(),
+            }
+        } else {
+            handler_future.await
+        }
+    }
+}
+
+/// Find the handler for `method`, falling back to GET for HEAD, then to `any`.
+fn find_handler<'a, S>(
+    method_handlers: &'a MethodHandlers<S>,
+    method: &Method,
+) -> Option<&'a (BoxedHandler<S>, SharedMiddlewares<S>)> {
+    method_handlers
+        .get(method)
+        .or_else(|| {
+            (*method == Method::HEAD)
+                .then(|| method_handlers.get(&Method::GET))
+                .flatten()
+        })
+        .or_else(|| method_handlers.get(&*ANY))
+}
+
+/// Request extension marking a path that matched no route, so middleware
+/// such as [`Cors`](crate::Cors) can leave it to the fallback or the 404.
+#[derive(Clone, Copy)]
+pub(crate) struct UnmatchedPath;
+
+/// Find the fallback with the longest prefix covering `path`.
+fn find_fallback<'a, S>(
+    fallbacks: &'a [FlatFallback<S>],
+    path: &str,
+) -> Option<&'a FlatFallback<S>> {
+    fallbacks.iter().find(|(prefix, _, _)| {
+        path.strip_prefix(prefix.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'))
+    })
+}
+
+/// Combine global middleware with route middleware, global first.
+fn with_global<S>(
+    global: &SharedMiddlewares<S>,
+    route: SharedMiddlewares<S>,
+) -> SharedMiddlewares<S> {
+    if route.is_empty() {
+        Arc::clone(global)
+    } else if global.is_empty() {
+        route
+    } else {
+        let mut combined = Vec::with_capacity(global.len() + route.len());
+        combined.extend_from_slice(global);
+        combined.extend_from_slice(&route);
+        Arc::new(combined)
+    }
+}
+
+/// Drop the body of a HEAD response, keeping the `Content-Length` it would have had.
+fn strip_body(response: Response<BoxBody>) -> Response<BoxBody> {
+    let (mut parts, body) = response.into_parts();
+    if !parts.headers.contains_key(hyper::header::CONTENT_LENGTH) {
+        if let Some(len) = body.size_hint().exact() {
+            parts
+                .headers
+                .insert(hyper::header::CONTENT_LENGTH, len.into());
+        }
+    }
+    let empty = Full::new(Bytes::new()).map_err(|e| match e {}).boxed();
+    Response::from_parts(parts, empty)
 }
 
 impl<S> Default for Foton<S>
@@ -536,16 +984,23 @@ where
     fn default() -> Self {
         Self {
             routes: Vec::new(),
+            fallback: None,
+            fallbacks: Vec::new(),
+            fallback_routes: Vec::new(),
             middlewares: Vec::new(),
+            global_middlewares: Arc::new(Vec::new()),
             state: None,
             router: None,
             error_handler: None,
             body_limit: None,
+            path_error_status: 400,
             request_timeout: None,
             handler_timeout: None,
             http2_enabled: false,
             max_connections: None,
             keep_alive: None,
+            trusted_proxies: Arc::new([]),
+            proxy_protocol: false,
         }
     }
 }
@@ -557,8 +1012,10 @@ where
 /// ```rust
 /// use foton::app;
 ///
+/// use foton::Req;
+///
 /// let mut app = app();
-/// app.get("/", |_| async { "Hello" });
+/// app.get("/", |_: Req| async { "Hello" });
 /// ```
 pub fn app() -> Foton {
     Foton::new()
@@ -566,26 +1023,124 @@ pub fn app() -> Foton {
 
 /// Create an HTTP application with custom state.
 ///
-/// State is shared across all handlers and accessed via the `State<S>` extractor.
+/// State is shared across all handlers and accessed via the `State<S>` extractor,
+/// or via `StateRef<S>` when `S` is not `Clone`.
 ///
 /// # Example
 ///
 /// ```rust
 /// use foton::{app_with_state, State};
 ///
+/// #[derive(Clone)]
 /// struct AppState {
-///     db: Database,
+///     name: String,
 /// }
 ///
-/// let mut app = app_with_state(AppState { db });
+/// let mut app = app_with_state(AppState { name: "foton".into() });
 /// app.get("/", |State(state): State<AppState>| async move {
-///     // Access state.db
+///     state.name
 /// });
 /// ```
 pub fn app_with_state<S: Send + Sync + 'static>(state: S) -> Foton<S> {
     Foton::with_state(state)
 }
 
+/// In-flight request tracking for keep-alive idle timeouts.
+struct ConnActivity {
+    in_flight: AtomicUsize,
+    last_active: Mutex<Instant>,
+}
+
+impl ConnActivity {
+    fn new() -> Self {
+        Self {
+            in_flight: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn begin(&self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn end(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Resolve once no request has been in flight for `timeout`. Never resolves without a timeout.
+    async fn idle(&self, timeout: Option<Duration>) {
+        let Some(timeout) = timeout else {
+            return std::future::pending().await;
+        };
+
+        loop {
+            if self.in_flight.load(Ordering::Relaxed) > 0 {
+                tokio::time::sleep(timeout).await;
+                continue;
+            }
+
+            let deadline = *self.last_active.lock().unwrap() + timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+/// Marks a request in flight on its connection until dropped.
+struct ActivityGuard(Arc<ConnActivity>);
+
+impl ActivityGuard {
+    fn begin(activity: Arc<ConnActivity>) -> Self {
+        activity.begin();
+        Self(activity)
+    }
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        self.0.end();
+    }
+}
+
+/// Response body holding its request's [`ActivityGuard`], so streamed bodies
+/// are not cut off by the keep-alive idle timeout.
+struct TrackedBody {
+    body: BoxBody,
+    _guard: ActivityGuard,
+}
+
+impl TrackedBody {
+    fn new(body: BoxBody, guard: ActivityGuard) -> Self {
+        Self {
+            body,
+            _guard: guard,
+        }
+    }
+}
+
+impl Body for TrackedBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<std::result::Result<hyper::body::Frame<Bytes>, Error>>> {
+        std::pin::Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.body.size_hint()
+    }
+}
+
 async fn shutdown_signal() -> std::io::Result<()> {
     #[cfg(unix)]
     {
@@ -605,3 +1160,670 @@ async fn shutdown_signal() -> std::io::Result<()> {
 
     Ok(())
 }
+
+/// Position of `method` in the `Allow` header.
+fn method_rank(method: &Method) -> usize {
+    const ORDER: [Method; 9] = [
+        Method::GET,
+        Method::HEAD,
+        Method::POST,
+        Method::PUT,
+        Method::PATCH,
+        Method::DELETE,
+        Method::OPTIONS,
+        Method::TRACE,
+        Method::CONNECT,
+    ];
+    ORDER
+        .iter()
+        .position(|m| m == method)
+        .unwrap_or(ORDER.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Res;
+    use crate::extractors::BodyBytes;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+
+    #[tokio::test]
+    async fn test_slow_body_times_out() {
+        let mut app = Foton::new();
+        app.set_request_timeout(Duration::from_millis(50));
+        app.post("/", |BodyBytes(body): BodyBytes| async move {
+            body.len().to_string()
+        });
+        app.build().unwrap();
+
+        let body = StreamBody::new(futures_util::stream::pending::<
+            std::result::Result<Frame<Bytes>, Infallible>,
+        >());
+        let req = Request::post("/").body(body).unwrap();
+
+        let res = app.handle_request(req, None).await.unwrap();
+        assert_eq!(res.status(), 408);
+    }
+
+    #[tokio::test]
+    async fn test_error_handler_renders_all_errors() {
+        use crate::extractors::Json;
+
+        let mut app = Foton::new();
+        app.set_error_handler(|error: Error| {
+            Res::builder()
+                .status(error.status_code())
+                .json(&serde_json::json!({ "error": error.message() }))
+        });
+        app.set_handler_timeout(Duration::from_millis(20));
+        app.post("/json", |Json(v): Json<serde_json::Value>| async move {
+            Res::json(&v)
+        });
+        app.get("/fail", |_: Req| async {
+            Err::<Res, _>(Error::forbidden("nope"))
+        });
+        app.get("/slow", |_: Req| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "late"
+        });
+
+        let client = app.into_test_client();
+
+        let res = client.post("/json").text("not json").send().await;
+        assert_eq!(res.status_code(), 400);
+        let body: serde_json::Value = res.into_json().await.unwrap();
+        assert_eq!(body["error"], "Content-Type must be application/json");
+
+        let res = client.get("/fail").send().await;
+        assert_eq!(res.status_code(), 403);
+        let body: serde_json::Value = res.into_json().await.unwrap();
+        assert_eq!(body["error"], "nope");
+
+        let res = client.get("/missing").send().await;
+        assert_eq!(res.status_code(), 404);
+        assert_eq!(res.headers()["content-type"], "application/json");
+
+        let res = client.delete("/fail").send().await;
+        assert_eq!(res.status_code(), 405);
+        assert_eq!(res.headers()["allow"], "GET, HEAD");
+        assert_eq!(res.headers()["content-type"], "application/json");
+
+        let res = client.get("/slow").send().await;
+        assert_eq!(res.status_code(), 500);
+        let body: serde_json::Value = res.into_json().await.unwrap();
+        assert!(
+            body["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Handler timeout")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_handler_keeps_middleware_headers() {
+        let mut app = Foton::new();
+        app.set_error_handler(|error: Error| {
+            Res::builder()
+                .status(error.status_code())
+                .json(&serde_json::json!({ "error": error.message() }))
+        });
+        app.attach(crate::from_fn(
+            |req: Req, _state: Arc<()>, next: crate::Next| async move {
+                let mut res = next.run(req).await;
+                res.headers_mut().insert(
+                    "access-control-allow-origin",
+                    hyper::header::HeaderValue::from_static("*"),
+                );
+                res
+            },
+        ));
+        app.attach(crate::from_fn(
+            |_req: Req, _state: Arc<()>, _next: crate::Next| async move {
+                Error::forbidden("blocked")
+                    .into_res()
+                    .header("x-reason", "policy")
+            },
+        ));
+        app.get("/", |_: Req| async { "ok" });
+        let client = app.into_test_client();
+
+        let res = client.get("/").send().await;
+        assert_eq!(res.status_code(), 403);
+        assert_eq!(res.headers()["access-control-allow-origin"], "*");
+        assert_eq!(res.headers()["x-reason"], "policy");
+        assert_eq!(res.headers()["content-type"], "application/json");
+        let body: serde_json::Value = res.into_json().await.unwrap();
+        assert_eq!(body["error"], "blocked");
+    }
+
+    #[tokio::test]
+    async fn test_method_routing() {
+        let mut app = Foton::new();
+        app.get("/page", |_: Req| async { "hello" });
+        app.any("/page", |req: Req| async move {
+            format!("any {}", req.method())
+        });
+        app.methods(
+            &[Method::PUT, Method::PATCH],
+            "/item",
+            |req: Req| async move { req.method().to_string() },
+        );
+        app.on(
+            Method::from_bytes(b"PURGE").unwrap(),
+            "/cache",
+            |_: Req| async { "purged" },
+        );
+        app.options("/cache", |_: Req| async { "options" });
+        app.route(crate::Route::trace("/trace", |_: Req| async { "trace" }));
+
+        let client = app.into_test_client();
+
+        // HEAD falls back to GET, keeping Content-Length but no body
+        let res = client.request(Method::HEAD, "/page").send().await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.headers()["content-length"], "5");
+        assert_eq!(res.into_bytes().await.unwrap(), "");
+
+        let res = client.post("/page").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "any POST");
+
+        let res = client.patch("/item").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "PATCH");
+        let res = client.get("/item").send().await;
+        assert_eq!(res.status_code(), 405);
+        assert_eq!(res.headers()["allow"], "PUT, PATCH");
+
+        let purge = Method::from_bytes(b"PURGE").unwrap();
+        let res = client.request(purge, "/cache").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "purged");
+        let res = client.request(Method::OPTIONS, "/cache").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "options");
+        let res = client.request(Method::TRACE, "/trace").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "trace");
+    }
+
+    #[tokio::test]
+    async fn test_method_routing_variants() {
+        let echo = |req: Req| async move { req.method().to_string() };
+        let purge = Method::from_bytes(b"PURGE").unwrap();
+        let mkcol = Method::from_bytes(b"MKCOL").unwrap();
+
+        let mut app = Foton::new();
+        app.connect("/tunnel", echo);
+        app.on(purge.clone(), "/all", echo);
+        app.delete("/all", echo);
+        app.trace("/all", echo);
+        app.on(mkcol.clone(), "/all", echo);
+        app.get("/all", echo);
+        app.options("/all", echo);
+        app.post("/all", echo);
+
+        let mut router = Router::new();
+        router.head("/head", |_: Req| async { Res::new().header("x-head", "1") });
+        router.options("/options", echo);
+        router.trace("/trace", echo);
+        router.connect("/connect", echo);
+        router.any("/any", echo);
+        router.on(purge.clone(), "/on", echo);
+        router.methods(&[Method::DELETE, Method::PUT], "/methods", echo);
+        app.nest("/router", router);
+
+        app.route(crate::Route::connect("/route/connect", echo));
+        app.route(crate::Route::any("/route/any", echo));
+        app.route(crate::Route::on(purge.clone(), "/route/on", echo));
+        app.route(crate::Route::methods(
+            &[Method::POST, Method::GET],
+            "/route/methods",
+            echo,
+        ));
+
+        let client = app.into_test_client();
+        let send = |method: Method, path: &'static str| {
+            let client = &client;
+            async move { client.request(method, path).send().await }
+        };
+
+        assert_eq!(
+            send(Method::CONNECT, "/tunnel")
+                .await
+                .into_text()
+                .await
+                .unwrap(),
+            "CONNECT"
+        );
+        let res = send(Method::PUT, "/all").await;
+        assert_eq!(res.status_code(), 405);
+        assert_eq!(
+            res.headers()["allow"],
+            "GET, HEAD, POST, DELETE, OPTIONS, TRACE, MKCOL, PURGE"
+        );
+
+        let res = send(Method::HEAD, "/router/head").await;
+        assert_eq!(res.headers()["x-head"], "1");
+        for (method, path) in [
+            (Method::OPTIONS, "/router/options"),
+            (Method::TRACE, "/router/trace"),
+            (Method::CONNECT, "/router/connect"),
+            (Method::PATCH, "/router/any"),
+            (purge.clone(), "/router/on"),
+            (Method::PUT, "/router/methods"),
+            (Method::DELETE, "/router/methods"),
+            (Method::CONNECT, "/route/connect"),
+            (mkcol.clone(), "/route/any"),
+            (purge.clone(), "/route/on"),
+            (Method::POST, "/route/methods"),
+            (Method::GET, "/route/methods"),
+        ] {
+            let res = send(method.clone(), path).await;
+            assert_eq!(res.into_text().await.unwrap(), method.as_str(), "{path}");
+        }
+
+        let res = send(Method::GET, "/router/methods").await;
+        assert_eq!(res.headers()["allow"], "PUT, DELETE");
+        let res = send(Method::GET, "/router/connect").await;
+        assert_eq!(res.headers()["allow"], "CONNECT");
+        let res = send(Method::PUT, "/route/methods").await;
+        assert_eq!(res.headers()["allow"], "GET, HEAD, POST");
+    }
+
+    #[tokio::test]
+    async fn test_fallbacks() {
+        let tag = |name: &'static str| {
+            crate::from_fn(
+                move |req: Req, _state: Arc<()>, next: crate::Next| async move {
+                    let mut res = next.run(req).await;
+                    res.headers_mut()
+                        .append("x-layer", hyper::header::HeaderValue::from_static(name));
+                    res
+                },
+            )
+        };
+        let layers = |res: &Res| {
+            res.headers()
+                .get_all("x-layer")
+                .iter()
+                .map(|v| v.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut v1 = Router::new();
+        v1.get("/ping", |_: Req| async { "pong" });
+        v1.fallback(|_: Req| async { Res::status(404).header("x-fallback", "v1") });
+
+        let mut api = Router::new();
+        api.attach(tag("api"));
+        api.nest("/v1", v1);
+        api.fallback(|_: Req| async {
+            Res::builder()
+                .status(404)
+                .json(&serde_json::json!({ "error": "no such endpoint" }))
+        });
+
+        let mut app = Foton::new();
+        app.attach(tag("global"));
+        app.nest("/api", api);
+        app.get("/", |_: Req| async { "home" });
+        app.fallback(|_: Req| async { Res::builder().status(404).html("<h1>Not here</h1>") });
+
+        let client = app.into_test_client();
+
+        let res = client.get("/api/v1/missing").send().await;
+        assert_eq!(res.status_code(), 404);
+        assert_eq!(res.headers()["x-fallback"], "v1");
+        assert_eq!(layers(&res), ["api", "global"]);
+
+        let res = client.get("/api/users").send().await;
+        assert_eq!(res.headers()["content-type"], "application/json");
+        assert_eq!(layers(&res), ["api", "global"]);
+
+        // Prefixes match whole segments
+        let res = client.get("/apiary").send().await;
+        assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+        assert_eq!(layers(&res), ["global"]);
+
+        // Matched paths with other methods still get 405
+        let res = client.post("/api/v1/ping").send().await;
+        assert_eq!(res.status_code(), 405);
+
+        let mut app = Foton::new();
+        app.attach(tag("global"));
+        let res = app.into_test_client().get("/nothing").send().await;
+        assert_eq!(res.status_code(), 404);
+        assert_eq!(layers(&res), ["global"]);
+    }
+
+    #[test]
+    fn test_build_reports_route_issues() {
+        let mut app = Foton::new();
+        app.get("/users/{id}", |_: Req| async { "user" });
+        app.get("/users/{name}", |_: Req| async { "conflict" });
+        app.get("/users/new", |_: Req| async { "static segments are fine" });
+        app.post("/users", |_: Req| async { "first" });
+        app.post("/users", |_: Req| async { "second" });
+        app.get("/files/{*path}/raw", |_: Req| async { "invalid" });
+        app.get("health", |_: Req| async { "no leading slash" });
+
+        let error = app.build().unwrap_err();
+        assert_eq!(
+            error.issues(),
+            [
+                RouteIssue::Duplicate {
+                    method: Method::POST,
+                    path: "/users".to_string(),
+                },
+                RouteIssue::Conflict {
+                    path: "/users/{name}".to_string(),
+                    existing: "/users/{id}".to_string(),
+                },
+                RouteIssue::Invalid {
+                    path: "/files/{*path}/raw".to_string(),
+                    reason: "Catch-all parameters are only allowed at the end of a route"
+                        .to_string(),
+                },
+                RouteIssue::Invalid {
+                    path: "health".to_string(),
+                    reason: "Route paths must start with '/'".to_string(),
+                },
+            ]
+        );
+
+        let mut app = Foton::new();
+        app.get("/", |_: Req| async { "ok" });
+        assert!(app.build().is_ok());
+        app.get("/late", |_: Req| async { "added after build" });
+        assert!(app.build().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "route methods must not be empty")]
+    fn test_route_without_methods() {
+        crate::Route::<()>::methods(&[], "/", |_: Req| async { "unreachable" });
+    }
+
+    #[tokio::test]
+    async fn test_route_issue_kinds() {
+        let issues = |app: &mut Foton| app.build().unwrap_err().issues().to_vec();
+
+        // Duplicates across Foton, nested routers and `Route::methods`
+        let mut app = Foton::new();
+        let mut router = Router::new();
+        router.get("/a", |_: Req| async { "first" });
+        app.nest("/v1", router);
+        app.route(crate::Route::methods(
+            &[Method::PUT, Method::GET],
+            "/v1/a",
+            |_: Req| async { "second" },
+        ));
+        let duplicate = RouteIssue::Duplicate {
+            method: Method::GET,
+            path: "/v1/a".to_string(),
+        };
+        assert_eq!(issues(&mut app), std::slice::from_ref(&duplicate));
+        assert_eq!(
+            duplicate.to_string(),
+            "GET /v1/a is registered more than once"
+        );
+
+        // Conflicts, including nested catch-alls
+        let mut app = Foton::new();
+        app.get("/files/{*path}", |_: Req| async { "files" });
+        let mut router = Router::new();
+        router.get("/{*rest}", |_: Req| async { "nested" });
+        app.nest("/files", router);
+        let conflict = RouteIssue::Conflict {
+            path: "/files/{*rest}".to_string(),
+            existing: "/files/{*path}".to_string(),
+        };
+        assert_eq!(issues(&mut app), std::slice::from_ref(&conflict));
+        assert_eq!(
+            conflict.to_string(),
+            "route /files/{*rest} conflicts with /files/{*path}"
+        );
+
+        // Invalid patterns
+        let mut app = Foton::new();
+        app.get("/users/{id", |_: Req| async { "unclosed" });
+        let [RouteIssue::Invalid { path, reason }] = &issues(&mut app)[..] else {
+            panic!("expected a single invalid route");
+        };
+        assert_eq!(path, "/users/{id");
+        assert!(
+            RouteIssue::Invalid {
+                path: path.clone(),
+                reason: reason.clone(),
+            }
+            .to_string()
+            .starts_with("invalid route /users/{id: ")
+        );
+
+        // `listen` reports every issue as `Error::Route`
+        let mut app = Foton::new();
+        app.get("/x", |_: Req| async { "x" });
+        app.get("/x", |_: Req| async { "x" });
+        app.get("x", |_: Req| async { "x" });
+        let error = app.listen(([127, 0, 0, 1], 0)).await.unwrap_err();
+        let Error::Route(route_error) = &error else {
+            panic!("expected Error::Route, got {error:?}");
+        };
+        assert_eq!(route_error.issues().len(), 2);
+        assert_eq!(error.status_code(), 500);
+        assert_eq!(
+            error.to_string(),
+            "Invalid routes: GET /x is registered more than once; \
+             invalid route x: Route paths must start with '/'"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http1_and_http2_on_same_listener() {
+        use http_body_util::{BodyExt, Empty};
+        use hyper::client::conn::{http1 as client_http1, http2 as client_http2};
+
+        let mut app = Foton::new();
+        app.set_http2(true);
+        app.get(
+            "/",
+            |req: Req| async move { format!("{:?}", req.version()) },
+        );
+        app.build().unwrap();
+        let app = Arc::new(app);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        // HTTP/1.1
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(Arc::clone(&app).serve_connection(
+            TokioIo::new(server),
+            None,
+            true,
+            shutdown_rx.clone(),
+        ));
+        let (mut sender, conn) = client_http1::handshake(TokioIo::new(client)).await.unwrap();
+        tokio::spawn(conn);
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HTTP/1.1");
+
+        // HTTP/2 with prior knowledge
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(Arc::clone(&app).serve_connection(
+            TokioIo::new(server),
+            None,
+            true,
+            shutdown_rx.clone(),
+        ));
+        let (mut sender, conn) =
+            client_http2::handshake(TokioExecutor::new(), TokioIo::new(client))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+        let req = Request::get("http://localhost/")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HTTP/2.0");
+    }
+
+    #[tokio::test]
+    async fn test_connect_info_addresses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        async fn fetch(addr: SocketAddr, prefix: &str) -> String {
+            let mut stream = loop {
+                if let Ok(stream) = TcpStream::connect(addr).await {
+                    break stream;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            };
+            let req = format!("{prefix}GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+            stream.write_all(req.as_bytes()).await.unwrap();
+            let mut res = String::new();
+            stream.read_to_string(&mut res).await.unwrap();
+            res.rsplit("\r\n\r\n").next().unwrap().to_string()
+        }
+
+        for proxy in [false, true] {
+            let addr = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap();
+            let mut app = Foton::new();
+            app.set_proxy_protocol(proxy);
+            app.get("/", |info: ConnectInfo| async move {
+                format!("{} {}", info.remote_addr, info.local_addr)
+            });
+            tokio::spawn(app.listen(addr));
+
+            if proxy {
+                let body = fetch(addr, "PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n").await;
+                assert_eq!(body, "192.0.2.1:56324 198.51.100.1:443");
+            } else {
+                let body = fetch(addr, "").await;
+                assert!(body.starts_with("127.0.0.1:"));
+                assert!(body.ends_with(&format!(" {}", addr)));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protocols_on_one_port() {
+        use http_body_util::Empty;
+        use hyper::client::conn::{http1 as client_http1, http2 as client_http2};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut app = Foton::new();
+        app.set_http2(true);
+        app.get(
+            "/",
+            |req: Req| async move { format!("{:?}", req.version()) },
+        );
+        tokio::spawn(app.listen(addr));
+        let connect = || async move {
+            loop {
+                if let Ok(stream) = TcpStream::connect(addr).await {
+                    return stream;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+
+        // HTTP/1.1
+        let (mut sender, conn) = client_http1::handshake(TokioIo::new(connect().await))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HTTP/1.1");
+
+        // HTTP/2 with prior knowledge
+        let (mut sender, conn) =
+            client_http2::handshake(TokioExecutor::new(), TokioIo::new(connect().await))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+        let req = Request::get(format!("http://{}/", addr))
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HTTP/2.0");
+
+        // `Upgrade: h2c` is ignored and answered over HTTP/1.1
+        let mut stream = connect().await;
+        stream
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings, close\r\n\
+                  Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("HTTP/1.1"));
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_waits_for_streamed_body() {
+        use http_body_util::Empty;
+        use hyper::client::conn::http1 as client_http1;
+
+        let mut app = Foton::new();
+        app.set_keep_alive(Duration::from_millis(50));
+        app.get("/", |_: Req| async {
+            Res::stream(|mut tx| async move {
+                for i in 0..5 {
+                    tokio::time::sleep(Duration::from_millis(40)).await;
+                    let _ = tx.send_text(i.to_string()).await;
+                }
+            })
+        });
+        app.build().unwrap();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(Arc::new(app).serve_connection(
+            TokioIo::new(server),
+            None,
+            false,
+            shutdown_rx,
+        ));
+        let (mut sender, conn) = client_http1::handshake(TokioIo::new(client)).await.unwrap();
+        tokio::spawn(conn);
+
+        // Streaming outlasts the idle timeout; the connection stays usable
+        for _ in 0..2 {
+            sender.ready().await.unwrap();
+            let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+            let res = sender.send_request(req).await.unwrap();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "01234");
+        }
+
+        // Idle once the body is finished: the connection is closed
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert!(sender.ready().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_idle_waits_for_in_flight_requests() {
+        let activity = ConnActivity::new();
+        let timeout = Some(Duration::from_millis(20));
+
+        activity.begin();
+        let idle = tokio::time::timeout(Duration::from_millis(60), activity.idle(timeout)).await;
+        assert!(idle.is_err());
+
+        activity.end();
+        let idle = tokio::time::timeout(Duration::from_millis(60), activity.idle(timeout)).await;
+        assert!(idle.is_ok());
+    }
+}
@@ -0,0 +1,291 @@
+//! `Authorization` header extractors.
+//!
+//! Missing or malformed credentials are rejected with 401 and a
+//! `WWW-Authenticate` challenge for the expected scheme.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::{BasicAuth, BearerToken, Error, Foton, Res};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!
+//!     app.get("/api", |BearerToken(token): BearerToken| async move {
+//!         Res::text(format!("token has {} bytes", token.len()))
+//!     });
+//!
+//!     app.get("/admin", |auth: BasicAuth| async move {
+//!         if auth.username == "admin" && auth.password == "hunter2" {
+//!             Ok(Res::text("welcome"))
+//!         } else {
+//!             Err(Error::forbidden("Wrong credentials"))
+//!         }
+//!     });
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_trait::async_trait;
+use hyper::header;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::extractors::reject_with;
+use crate::{Error, FromRequest, Req, Result};
+
+/// Token from an `Authorization: Bearer <token>` header.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BearerToken(pub String);
+
+impl fmt::Debug for BearerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BearerToken").field(&"<redacted>").finish()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for BearerToken
+where
+    S: Send + Sync + 'static,
+{
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        bearer_token(req, None).map(BearerToken)
+    }
+}
+
+/// Credentials from an `Authorization: Basic` header.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BasicAuth {
+    /// User ID.
+    pub username: String,
+    /// Password; may be empty.
+    pub password: String,
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+const BASIC_CHALLENGE: &str = "Basic realm=\"Restricted\", charset=\"UTF-8\"";
+
+#[async_trait]
+impl<S> FromRequest<S> for BasicAuth
+where
+    S: Send + Sync + 'static,
+{
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        let Some(encoded) = credentials(req, "Basic") else {
+            return Err(basic_error(req, "Missing basic credentials"));
+        };
+        let decoded = decode_base64(encoded).and_then(|bytes| String::from_utf8(bytes).ok());
+        let Some((username, password)) = decoded.as_deref().and_then(|d| d.split_once(':')) else {
+            return Err(basic_error(req, "Malformed basic credentials"));
+        };
+
+        Ok(BasicAuth {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+}
+
+/// Decode standard, padded base64.
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    fn sextet(b: u8) -> Option<u32> {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        Some(value.into())
+    }
+
+    let input = input.as_bytes();
+    if input.len() % 4 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    let mut chunks = input.chunks(4).peekable();
+    while let Some(chunk) = chunks.next() {
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && chunks.peek().is_some()) {
+            return None;
+        }
+        let mut n = 0;
+        for &b in &chunk[..4 - padding] {
+            n = n << 6 | sextet(b)?;
+        }
+        n <<= 6 * padding;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+/// 401 with a basic challenge.
+fn basic_error(req: &mut Req, msg: &str) -> Error {
+    reject_with(
+        req,
+        Error::unauthorized(msg),
+        header::WWW_AUTHENTICATE,
+        BASIC_CHALLENGE,
+    )
+}
+
+/// Credentials following `scheme` in the `Authorization` header.
+///
+/// The scheme is matched case-insensitively.
+fn credentials<'a>(req: &'a Req, scheme: &str) -> Option<&'a str> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (given, rest) = value.split_once(' ')?;
+    given
+        .eq_ignore_ascii_case(scheme)
+        .then(|| rest.trim())
+        .filter(|rest| !rest.is_empty())
+}
+
+/// Extract a bearer token, rejecting with an RFC 6750 challenge.
+pub(crate) fn bearer_token(req: &mut Req, realm: Option<&str>) -> Result<String> {
+    match credentials(req, "Bearer") {
+        Some(token) if !token.contains(' ') => Ok(token.to_string()),
+        Some(_) => Err(bearer_error(
+            req,
+            realm,
+            Error::unauthorized("Malformed bearer token"),
+        )),
+        None => Err(reject_with(
+            req,
+            Error::unauthorized("Missing bearer token"),
+            header::WWW_AUTHENTICATE,
+            &bearer_challenge(realm, None),
+        )),
+    }
+}
+
+/// Reject a bearer token that was present but not accepted, describing
+/// `error` in the challenge.
+pub(crate) fn bearer_error(req: &mut Req, realm: Option<&str>, error: Error) -> Error {
+    let challenge = bearer_challenge(realm, Some(&error.message()));
+    reject_with(req, error, header::WWW_AUTHENTICATE, &challenge)
+}
+
+fn bearer_challenge(realm: Option<&str>, invalid: Option<&str>) -> String {
+    let mut params = Vec::new();
+    if let Some(realm) = realm {
+        params.push(format!("realm=\"{}\"", realm.replace(['"', '\\'], "")));
+    }
+    if let Some(description) = invalid {
+        params.push("error=\"invalid_token\"".to_string());
+        params.push(format!(
+            "error_description=\"{}\"",
+            description.replace(['"', '\\'], "")
+        ));
+    }
+    if params.is_empty() {
+        "Bearer".to_string()
+    } else {
+        format!("Bearer {}", params.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Foton, Res};
+
+    #[tokio::test]
+    async fn test_bearer_token() {
+        let mut app = Foton::new();
+        app.get("/", |BearerToken(token): BearerToken| async move { token });
+        let client = app.into_test_client();
+
+        let res = client
+            .get("/")
+            .header("authorization", "bearer abc.def")
+            .send()
+            .await;
+        assert_eq!(res.into_text().await.unwrap(), "abc.def");
+
+        let res = client.get("/").send().await;
+        assert_eq!(res.status_code(), 401);
+        assert_eq!(res.headers()["www-authenticate"], "Bearer");
+
+        let res = client
+            .get("/")
+            .header("authorization", "Bearer a b")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 401);
+        assert_eq!(
+            res.headers()["www-authenticate"],
+            "Bearer error=\"invalid_token\", error_description=\"Malformed bearer token\""
+        );
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("Zg==").unwrap(), b"f");
+        assert_eq!(decode_base64("Zm8=").unwrap(), b"fo");
+        assert_eq!(decode_base64("Zm9v").unwrap(), b"foo");
+        assert_eq!(decode_base64("+/+/").unwrap(), [0xfb, 0xff, 0xbf]);
+        assert_eq!(decode_base64("YWxpY2U6cGE6c3M=").unwrap(), b"alice:pa:ss");
+        for invalid in ["Zg", "Zg=", "Z===", "Zg==Zm9v", "Zm9v!", "Zm-_"] {
+            assert!(decode_base64(invalid).is_none(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_basic_auth() {
+        let mut app = Foton::new();
+        app.set_error_handler(|error: Error| match error {
+            Error::Status(401, Some(msg)) => Res::builder().status(401).text(msg),
+            other => Res::builder().status(other.status_code()).text(""),
+        });
+        app.get("/", |auth: BasicAuth| async move {
+            format!("{}:{}", auth.username, auth.password)
+        });
+        app.get(
+            "/optional",
+            |auth: std::result::Result<BasicAuth, Error>| async move {
+                auth.map_or_else(|e| e.message(), |auth| auth.username)
+            },
+        );
+        let client = app.into_test_client();
+
+        let res = client
+            .get("/")
+            .header("authorization", "Basic YWxpY2U6cGE6c3M=")
+            .send()
+            .await;
+        assert_eq!(res.into_text().await.unwrap(), "alice:pa:ss");
+
+        // The challenge survives a custom error handler matching on the status
+        for (auth, msg) in [
+            ("", "Missing basic credentials"),
+            ("Basic !!!", "Malformed basic credentials"),
+            ("Bearer token", "Missing basic credentials"),
+        ] {
+            let res = client.get("/").header("authorization", auth).send().await;
+            assert_eq!(res.status_code(), 401);
+            assert_eq!(res.headers()["www-authenticate"], BASIC_CHALLENGE);
+            assert_eq!(res.into_text().await.unwrap(), msg);
+        }
+
+        // Handlers taking the rejection themselves send no challenge
+        let res = client.get("/optional").send().await;
+        assert_eq!(res.status_code(), 200);
+        assert!(res.headers().get("www-authenticate").is_none());
+        assert_eq!(res.into_text().await.unwrap(), "Missing basic credentials");
+    }
+}
@@ -0,0 +1,596 @@
+//! Response compression middleware.
+//!
+//! Enable with the `compression` feature flag.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::{Compression, Foton, Req, Res, Router};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!     app.attach(Compression::new());
+//!
+//!     // Or only for some routes
+//!     let mut api = Router::new();
+//!     api.attach(Compression::new().min_size(256));
+//!     api.get("/report", |_: Req| async { Res::text("...") });
+//!     app.nest("/api", api);
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_compression::Level;
+use async_compression::tokio::write::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{FutureExt, Stream, StreamExt, TryStreamExt};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Body, Frame};
+use hyper::{Method, StatusCode, header};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::res::{BoxBody, append_vary};
+use crate::{Middleware, Next, Req, Res, Result};
+
+/// Compressed output is sent once this much has accumulated, even while more
+/// input is ready.
+const OUTPUT_CHUNK: usize = 16 * 1024;
+
+/// Content coding produced by [`Compression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Coding {
+    fn name(self) -> &'static str {
+        match self {
+            Coding::Brotli => "br",
+            Coding::Zstd => "zstd",
+            Coding::Gzip => "gzip",
+        }
+    }
+}
+
+/// Middleware compressing response bodies with gzip, brotli or zstd.
+///
+/// The coding is negotiated from `Accept-Encoding` q-values. Responses with
+/// already-compressed content types (images, audio, video, archives, fonts),
+/// known sizes below [`min_size`](Compression::min_size), an existing
+/// `Content-Encoding` or `Cache-Control: no-transform` are passed through.
+/// Streaming bodies are compressed on the fly and flushed whenever the
+/// handler pauses, so Server-Sent Events still arrive promptly.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    gzip: bool,
+    brotli: bool,
+    zstd: bool,
+    min_size: u64,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            gzip: true,
+            brotli: true,
+            zstd: true,
+            min_size: 1024,
+        }
+    }
+}
+
+impl Compression {
+    /// Enable all codings with a 1 KiB minimum size.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable gzip.
+    pub fn gzip(mut self, enabled: bool) -> Self {
+        self.gzip = enabled;
+        self
+    }
+
+    /// Enable or disable brotli.
+    pub fn brotli(mut self, enabled: bool) -> Self {
+        self.brotli = enabled;
+        self
+    }
+
+    /// Enable or disable zstd.
+    pub fn zstd(mut self, enabled: bool) -> Self {
+        self.zstd = enabled;
+        self
+    }
+
+    /// Skip bodies smaller than `bytes`, going by the body's exact size or its
+    /// `Content-Length` header.
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    fn enabled(&self, coding: Coding) -> bool {
+        match coding {
+            Coding::Brotli => self.brotli,
+            Coding::Zstd => self.zstd,
+            Coding::Gzip => self.gzip,
+        }
+    }
+
+    /// Pick the enabled coding with the highest q-value; ties go to br, zstd, gzip.
+    fn negotiate(&self, accept_encoding: &str) -> Option<Coding> {
+        let mut wildcard = None;
+        let mut explicit: Vec<(&str, f32)> = Vec::new();
+
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if name == "*" {
+                wildcard = Some(q);
+            } else if !name.is_empty() {
+                explicit.push((name, q));
+            }
+        }
+
+        let mut best: Option<(Coding, f32)> = None;
+        for coding in [Coding::Brotli, Coding::Zstd, Coding::Gzip] {
+            if !self.enabled(coding) {
+                continue;
+            }
+            let q = explicit
+                .iter()
+                .find(|(name, _)| {
+                    name.eq_ignore_ascii_case(coding.name())
+                        || (coding == Coding::Gzip && name.eq_ignore_ascii_case("x-gzip"))
+                })
+                .map(|(_, q)| *q)
+                .or(wildcard)
+                .unwrap_or(0.0);
+
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((coding, q));
+            }
+        }
+
+        best.map(|(coding, _)| coding)
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for Compression
+where
+    S: Send + Sync + 'static,
+{
+    async fn handle(&self, req: Req, _state: Arc<S>, next: Next<S>) -> Res {
+        let is_head = req.method() == Method::HEAD;
+        let coding = req
+            .header(header::ACCEPT_ENCODING.as_str())
+            .and_then(|accept| self.negotiate(accept));
+
+        let res = next.run(req).await;
+
+        // Errors are left for the ErrorHandler to render
+        if res.error().is_some() || !is_compressible(&res) {
+            return res;
+        }
+
+        let mut response = res.into_hyper();
+        append_vary(response.headers_mut(), "accept-encoding");
+
+        let Some(coding) = coding else {
+            return Res::from_hyper(response);
+        };
+        // Streamed bodies such as files only announce their size in the header
+        let size = response.body().size_hint().exact().or_else(|| {
+            response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+        });
+        if size.is_some_and(|size| size < self.min_size) {
+            return Res::from_hyper(response);
+        }
+
+        let (mut parts, body) = response.into_parts();
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.remove(header::ACCEPT_RANGES);
+        parts.headers.insert(
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static(coding.name()),
+        );
+
+        // The compressed representation is no longer byte-identical
+        if let Some(etag) = parts.headers.get(header::ETAG).cloned() {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = header::HeaderValue::from_bytes(&weak) {
+                    parts.headers.insert(header::ETAG, weak);
+                }
+            }
+        }
+
+        // HEAD announces the encoding a GET would get, with a body of unknown length
+        let body = if is_head {
+            BodyExt::boxed(StreamBody::new(
+                tokio_stream::empty::<Result<Frame<Bytes>>>(),
+            ))
+        } else {
+            compress(body, Encoder::new(coding))
+        };
+        Res::from_hyper(hyper::Response::from_parts(parts, body))
+    }
+}
+
+fn is_compressible(res: &Res) -> bool {
+    let status = res.status_code();
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || status == StatusCode::PARTIAL_CONTENT
+    {
+        return false;
+    }
+
+    let headers = res.headers();
+    if headers.contains_key(header::CONTENT_ENCODING) || headers.contains_key(header::CONTENT_RANGE)
+    {
+        return false;
+    }
+    if headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.to_ascii_lowercase().contains("no-transform"))
+    {
+        return false;
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+
+    match essence.split_once('/') {
+        Some(("image", subtype)) => subtype == "svg+xml" || subtype == "bmp" || subtype == "x-icon",
+        Some(("audio" | "video", _)) => false,
+        Some(("font", subtype)) => !subtype.starts_with("woff"),
+        Some(("application", subtype)) => !matches!(
+            subtype,
+            "zip"
+                | "gzip"
+                | "x-gzip"
+                | "zstd"
+                | "x-brotli"
+                | "x-bzip2"
+                | "x-xz"
+                | "x-7z-compressed"
+                | "x-rar-compressed"
+                | "vnd.rar"
+                | "pdf"
+                | "wasm"
+                | "octet-stream"
+        ),
+        _ => true,
+    }
+}
+
+/// Streaming encoder writing into an in-memory buffer.
+enum Encoder {
+    Brotli(Box<BrotliEncoder<Vec<u8>>>),
+    Zstd(ZstdEncoder<Vec<u8>>),
+    Gzip(GzipEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(coding: Coding) -> Self {
+        match coding {
+            // Maximum brotli quality is far too slow for on-the-fly compression
+            Coding::Brotli => Self::Brotli(Box::new(BrotliEncoder::with_quality(
+                Vec::new(),
+                Level::Precise(4),
+            ))),
+            Coding::Zstd => Self::Zstd(ZstdEncoder::new(Vec::new())),
+            Coding::Gzip => Self::Gzip(GzipEncoder::new(Vec::new())),
+        }
+    }
+
+    fn writer(&mut self) -> &mut (dyn AsyncWrite + Send + Unpin) {
+        match self {
+            Self::Brotli(e) => e,
+            Self::Zstd(e) => e,
+            Self::Gzip(e) => e,
+        }
+    }
+
+    fn buffered(&self) -> usize {
+        match self {
+            Self::Brotli(e) => e.get_ref().len(),
+            Self::Zstd(e) => e.get_ref().len(),
+            Self::Gzip(e) => e.get_ref().len(),
+        }
+    }
+
+    fn take(&mut self) -> Bytes {
+        let buf = match self {
+            Self::Brotli(e) => e.get_mut(),
+            Self::Zstd(e) => e.get_mut(),
+            Self::Gzip(e) => e.get_mut(),
+        };
+        Bytes::from(std::mem::take(buf))
+    }
+}
+
+type DataStream = Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>;
+
+struct CompressState {
+    body: DataStream,
+    encoder: Encoder,
+    ready: Option<Option<Result<Bytes>>>,
+    finished: bool,
+}
+
+/// Compress a body on a background task, like [`Res::stream`].
+fn compress(body: BoxBody, encoder: Encoder) -> BoxBody {
+    let (tx, rx) = mpsc::channel::<Result<Bytes>>(8);
+    let mut state = CompressState {
+        body: Box::pin(body.into_data_stream()),
+        encoder,
+        ready: None,
+        finished: false,
+    };
+
+    tokio::spawn(async move {
+        loop {
+            match next_output(&mut state).await {
+                Ok(Some(chunk)) => {
+                    if tx.send(Ok(chunk)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    tx.send(Err(e)).await.ok();
+                    break;
+                }
+            }
+        }
+    });
+
+    BodyExt::boxed(StreamBody::new(ReceiverStream::new(rx).map_ok(Frame::data)))
+}
+
+/// Compress input until output should be sent, flushing whenever the input
+/// has nothing ready. Returns `None` once the encoder is finished.
+async fn next_output(state: &mut CompressState) -> Result<Option<Bytes>> {
+    if state.finished {
+        return Ok(None);
+    }
+
+    loop {
+        let item = match state.ready.take() {
+            Some(item) => item,
+            None => state.body.next().await,
+        };
+
+        match item {
+            Some(chunk) => {
+                state.encoder.writer().write_all(&chunk?).await?;
+
+                match state.body.next().now_or_never() {
+                    Some(item) => {
+                        state.ready = Some(item);
+                        if state.encoder.buffered() >= OUTPUT_CHUNK {
+                            return Ok(Some(state.encoder.take()));
+                        }
+                    }
+                    None => {
+                        state.encoder.writer().flush().await?;
+                        if state.encoder.buffered() > 0 {
+                            return Ok(Some(state.encoder.take()));
+                        }
+                    }
+                }
+            }
+            None => {
+                state.finished = true;
+                state.encoder.writer().shutdown().await?;
+                let rest = state.encoder.take();
+                return Ok((!rest.is_empty()).then_some(rest));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::TempPath;
+    use crate::{Foton, StreamSender};
+    use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZstdDecoder};
+    use tokio::io::AsyncReadExt;
+
+    async fn decode(coding: &str, body: Bytes) -> String {
+        let mut out = String::new();
+        let input = &body[..];
+        match coding {
+            "gzip" => GzipDecoder::new(input).read_to_string(&mut out).await,
+            "br" => BrotliDecoder::new(input).read_to_string(&mut out).await,
+            "zstd" => ZstdDecoder::new(input).read_to_string(&mut out).await,
+            other => panic!("unexpected coding {}", other),
+        }
+        .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let c = Compression::new();
+        assert_eq!(c.negotiate("gzip, deflate, br"), Some(Coding::Brotli));
+        assert_eq!(c.negotiate("gzip;q=1.0, br;q=0.5"), Some(Coding::Gzip));
+        assert_eq!(c.negotiate("br;q=0, *"), Some(Coding::Zstd));
+        assert_eq!(c.negotiate("identity"), None);
+        assert_eq!(c.negotiate("*;q=0"), None);
+        assert_eq!(c.brotli(false).negotiate("br, gzip"), Some(Coding::Gzip));
+    }
+
+    #[tokio::test]
+    async fn test_compresses_full_bodies() {
+        let text = "hello compression ".repeat(200);
+        let mut app = Foton::new();
+        app.attach(Compression::new());
+        let body = text.clone();
+        app.get("/", move |_: Req| {
+            let body = body.clone();
+            async move { Res::text(body) }
+        });
+        app.get("/small", |_: Req| async { Res::text("tiny") });
+        app.get("/png", |_: Req| async {
+            Res::builder()
+                .header("content-type", "image/png")
+                .body(vec![0u8; 4096])
+        });
+        let client = app.into_test_client();
+
+        for coding in ["gzip", "br", "zstd"] {
+            let res = client
+                .get("/")
+                .header("accept-encoding", coding)
+                .send()
+                .await;
+            assert_eq!(res.headers()["content-encoding"], coding);
+            assert_eq!(res.headers()["vary"], "accept-encoding");
+            assert!(res.headers().get("content-length").is_none());
+            let body = res.into_bytes().await.unwrap();
+            assert!(body.len() < text.len());
+            assert_eq!(decode(coding, body).await, text);
+        }
+
+        let res = client.get("/").send().await;
+        assert!(res.headers().get("content-encoding").is_none());
+        assert_eq!(res.headers()["vary"], "accept-encoding");
+
+        let res = client
+            .get("/small")
+            .header("accept-encoding", "gzip")
+            .send()
+            .await;
+        assert!(res.headers().get("content-encoding").is_none());
+
+        let res = client
+            .get("/png")
+            .header("accept-encoding", "gzip")
+            .send()
+            .await;
+        assert!(res.headers().get("content-encoding").is_none());
+        assert!(res.headers().get("vary").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_head_announces_encoding() {
+        let mut app = Foton::new();
+        app.attach(Compression::new());
+        app.get("/", |_: Req| async {
+            Res::text("hello compression ".repeat(200))
+        });
+        let client = app.into_test_client();
+
+        let res = client
+            .request(Method::HEAD, "/")
+            .header("accept-encoding", "gzip")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+        assert_eq!(res.headers()["vary"], "accept-encoding");
+        assert!(res.headers().get("content-length").is_none());
+        assert!(res.into_bytes().await.unwrap().is_empty());
+
+        let res = client.request(Method::HEAD, "/").send().await;
+        assert!(res.headers().get("content-encoding").is_none());
+        assert_eq!(res.headers()["vary"], "accept-encoding");
+        assert_eq!(res.headers()["content-length"], "3600");
+    }
+
+    #[tokio::test]
+    async fn test_min_size_uses_content_length() {
+        let path = TempPath::new("compress.txt");
+        tokio::fs::write(&path, "small file").await.unwrap();
+
+        let mut app = Foton::new();
+        app.attach(Compression::new());
+        let file = path.to_path_buf();
+        app.get("/file", move |_: Req| {
+            let file = file.clone();
+            async move { Res::file(file).await }
+        });
+        app.get("/stream", |_: Req| async {
+            Res::stream(|mut tx: StreamSender| async move {
+                tx.send_text("tiny").await.ok();
+            })
+            .header("content-type", "text/plain")
+            .header("content-length", "4")
+        });
+        let client = app.into_test_client();
+
+        for uri in ["/file", "/stream"] {
+            let res = client
+                .get(uri)
+                .header("accept-encoding", "gzip")
+                .send()
+                .await;
+            assert!(res.headers().get("content-encoding").is_none(), "{}", uri);
+            assert_eq!(res.headers()["vary"], "accept-encoding");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compresses_streams_incrementally() {
+        let mut app = Foton::new();
+        app.attach(Compression::new());
+        app.get("/stream", |_: Req| async {
+            Res::stream(|mut tx: StreamSender| async move {
+                tx.send_text("first ").await.ok();
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                tx.send_text("second").await.ok();
+            })
+            .header("content-type", "text/event-stream")
+        });
+
+        let res = app
+            .into_test_client()
+            .get("/stream")
+            .header("accept-encoding", "gzip")
+            .send()
+            .await;
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+
+        // The first chunk is flushed before the handler sends the second
+        let mut body = res.into_hyper().into_body();
+        let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        let mut rest = first.to_vec();
+        while let Some(frame) = body.frame().await {
+            rest.extend_from_slice(&frame.unwrap().into_data().unwrap());
+        }
+        assert!(!first.is_empty());
+        assert_eq!(decode("gzip", rest.into()).await, "first second");
+    }
+}
@@ -7,12 +7,14 @@ use std::time::Duration;
 use crate::{Error, Result};
 
 /// Server configuration.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ServerConfig {
     /// Maximum request body size in bytes.
     pub body_limit: Option<usize>,
 
     /// Request timeout in seconds.
+    ///
+    /// Bounds reading HTTP/1 request headers and uploading the request body.
     #[serde(default, with = "opt_duration_serde")]
     pub request_timeout: Option<Duration>,
 
@@ -20,31 +22,20 @@ pub struct ServerConfig {
     #[serde(default, with = "opt_duration_serde")]
     pub handler_timeout: Option<Duration>,
 
-    /// Enable HTTP/2 support.
+    /// Serve HTTP/2 alongside HTTP/1.1 on the same port.
     #[serde(default)]
     pub http2: bool,
 
     /// Maximum number of concurrent connections.
     pub max_connections: Option<usize>,
 
-    /// TCP keep-alive duration in seconds.
+    /// Keep-alive duration in seconds.
+    ///
+    /// Used as the TCP keep-alive time and as the idle timeout between requests.
     #[serde(default, with = "opt_duration_serde")]
     pub keep_alive: Option<Duration>,
 }
 
-impl Default for ServerConfig {
-    fn default() -> Self {
-        Self {
-            body_limit: None,
-            request_timeout: None,
-            handler_timeout: None,
-            http2: false,
-            max_connections: None,
-            keep_alive: None,
-        }
-    }
-}
-
 impl ServerConfig {
     /// Create a new empty configuration.
     pub fn new() -> Self {
@@ -0,0 +1,382 @@
+//! Connection and client address extractors.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::connect_info::Cidr;
+//! use foton::{ClientIp, ConnectInfo, Foton, Res};
+//!
+//! #[tokio::main]
+//! async fn main() -> foton::Result<()> {
+//!     let mut app = Foton::new();
+//!     // Believe forwarding headers only from the load balancer's subnet
+//!     app.set_trusted_proxies(["10.0.0.0/8".parse::<Cidr>()?]);
+//!
+//!     app.get("/whoami", |ClientIp(ip): ClientIp, info: ConnectInfo| async move {
+//!         Res::text(format!("{} via {}", ip, info.peer_addr))
+//!     });
+//!
+//!     app.listen(([0, 0, 0, 0], 3000)).await
+//! }
+//! ```
+
+use async_trait::async_trait;
+use hyper::header::{self, HeaderMap};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::{Error, FromRequest, Req, Result};
+
+/// Addresses of the connection a request arrived on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectInfo {
+    /// Client address: the peer, or the source reported by the PROXY protocol.
+    pub remote_addr: SocketAddr,
+    /// Address of the directly connected peer.
+    pub peer_addr: SocketAddr,
+    /// Server address the client connected to: the local end of the socket,
+    /// or the destination reported by the PROXY protocol.
+    pub local_addr: SocketAddr,
+}
+
+impl ConnectInfo {
+    /// Connection info for a peer at `addr` connecting directly to `local_addr`.
+    pub fn new(addr: SocketAddr, local_addr: SocketAddr) -> Self {
+        Self {
+            remote_addr: addr,
+            peer_addr: addr,
+            local_addr,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ConnectInfo
+where
+    S: Send + Sync + 'static,
+{
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        req.connect_info()
+            .ok_or_else(|| Error::internal("Connection info unavailable"))
+    }
+}
+
+/// Client IP address, read from forwarding headers set by trusted proxies.
+///
+/// When the connection comes from an address in
+/// [`Foton::set_trusted_proxies`](crate::Foton::set_trusted_proxies), the
+/// `Forwarded`, `X-Forwarded-For` or `X-Real-IP` header (in that order of
+/// preference) is followed back past every trusted hop. Otherwise the
+/// connection's address is used and the headers are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequest<S> for ClientIp
+where
+    S: Send + Sync + 'static,
+{
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        req.client_ip()
+            .map(ClientIp)
+            .ok_or_else(|| Error::internal("Connection info unavailable"))
+    }
+}
+
+/// IP network in CIDR notation, such as `10.0.0.0/8` or `2001:db8::/32`.
+///
+/// A bare address parses as a single-host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Create a network from an address and prefix length.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(Error::Custom(format!(
+                "Invalid CIDR prefix /{} for {}",
+                prefix, addr
+            )));
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    /// Whether `ip` is inside this network. IPv4-mapped IPv6 addresses match
+    /// IPv4 networks.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Custom(format!("Invalid CIDR: {}", s));
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| invalid())?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Cidr::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Resolve the client address of a connection from `peer`.
+pub(crate) fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[Cidr]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+    if !is_trusted(peer) {
+        return peer;
+    }
+
+    // Hops in order of arrival; `None` marks a hop that cannot be parsed
+    let hops: Vec<Option<IpAddr>> = if headers.contains_key(header::FORWARDED) {
+        headers
+            .get_all(header::FORWARDED)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value))
+            })
+            .collect()
+    } else if headers.contains_key("x-forwarded-for") {
+        headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(parse_node)
+            .collect()
+    } else {
+        headers
+            .get("x-real-ip")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| vec![parse_node(v)])
+            .unwrap_or_default()
+    };
+
+    // Walk back from the peer while each hop was added by a trusted proxy
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        match hop {
+            Some(ip) => client = ip,
+            None => break,
+        }
+        if !is_trusted(client) {
+            break;
+        }
+    }
+    client
+}
+
+/// Parse an address from a forwarding header, with optional quotes, brackets
+/// and port.
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Foton;
+
+    fn resolve(peer: &str, headers: &[(&str, &str)]) -> String {
+        let trusted: Vec<Cidr> = ["10.0.0.0/8", "2001:db8::/32"]
+            .iter()
+            .map(|c| c.parse().unwrap())
+            .collect();
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        resolve_client_ip(peer.parse().unwrap(), &map, &trusted).to_string()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(net.contains("192.168.4.2".parse().unwrap()));
+        assert!(net.contains("::ffff:192.168.4.2".parse().unwrap()));
+        assert!(!net.contains("192.169.0.1".parse().unwrap()));
+        assert!(
+            "0.0.0.0/0"
+                .parse::<Cidr>()
+                .unwrap()
+                .contains("8.8.8.8".parse().unwrap())
+        );
+        assert_eq!("::1".parse::<Cidr>().unwrap().to_string(), "::1/128");
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("nonsense".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        // Headers from untrusted peers are ignored
+        assert_eq!(
+            resolve("203.0.113.9", &[("x-forwarded-for", "1.2.3.4")]),
+            "203.0.113.9"
+        );
+
+        assert_eq!(
+            resolve(
+                "10.0.0.1",
+                &[("x-forwarded-for", "1.2.3.4, 5.6.7.8, 10.0.0.2")]
+            ),
+            "5.6.7.8"
+        );
+        assert_eq!(
+            resolve(
+                "10.0.0.1",
+                &[
+                    ("x-forwarded-for", "1.2.3.4"),
+                    ("x-forwarded-for", "10.1.1.1")
+                ]
+            ),
+            "1.2.3.4"
+        );
+        assert_eq!(
+            resolve(
+                "10.0.0.1",
+                &[(
+                    "forwarded",
+                    "for=192.0.2.60;proto=http, for=\"[2001:db8:cafe::17]:4711\""
+                )]
+            ),
+            "192.0.2.60"
+        );
+        assert_eq!(
+            resolve("2001:db8::1", &[("forwarded", "for=\"198.51.100.7:8080\"")]),
+            "198.51.100.7"
+        );
+        assert_eq!(
+            resolve("10.0.0.1", &[("x-real-ip", "198.51.100.7")]),
+            "198.51.100.7"
+        );
+        // Forwarded wins over X-Forwarded-For
+        assert_eq!(
+            resolve(
+                "10.0.0.1",
+                &[("forwarded", "for=1.1.1.1"), ("x-forwarded-for", "2.2.2.2")]
+            ),
+            "1.1.1.1"
+        );
+        // An unparsable hop stops the walk at the last trusted address
+        assert_eq!(
+            resolve(
+                "10.0.0.1",
+                &[("forwarded", "for=1.1.1.1, for=_hidden, for=10.0.0.5")]
+            ),
+            "10.0.0.5"
+        );
+        assert_eq!(resolve("10.0.0.1", &[]), "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn test_extractors() {
+        let mut app = Foton::new();
+        app.set_trusted_proxies(["127.0.0.1".parse::<Cidr>().unwrap()]);
+        app.get(
+            "/",
+            |ClientIp(ip): ClientIp, info: ConnectInfo| async move {
+                format!("{} {}", ip, info.remote_addr)
+            },
+        );
+        let client = app.into_test_client();
+
+        let res = client
+            .get("/")
+            .remote_addr(([127, 0, 0, 1], 5000))
+            .header("x-forwarded-for", "198.51.100.7")
+            .send()
+            .await;
+        assert_eq!(
+            res.into_text().await.unwrap(),
+            "198.51.100.7 127.0.0.1:5000"
+        );
+
+        let res = client.get("/").send().await;
+        assert_eq!(res.status_code(), 500);
+    }
+
+    #[tokio::test]
+    async fn test_connect_info() {
+        let mut app = Foton::new();
+        app.get("/", |info: ConnectInfo| async move {
+            format!(
+                "{} {} {}",
+                info.remote_addr, info.peer_addr, info.local_addr
+            )
+        });
+        app.get("/local", |req: Req| async move {
+            req.connect_info().unwrap().local_addr.to_string()
+        });
+        let client = app.into_test_client();
+
+        // As received through the PROXY protocol
+        let info = ConnectInfo {
+            remote_addr: ([192, 0, 2, 1], 56324).into(),
+            peer_addr: ([10, 0, 0, 2], 41000).into(),
+            local_addr: ([198, 51, 100, 1], 443).into(),
+        };
+        let res = client.get("/").connect_info(info).send().await;
+        assert_eq!(
+            res.into_text().await.unwrap(),
+            "192.0.2.1:56324 10.0.0.2:41000 198.51.100.1:443"
+        );
+
+        let res = client.get("/local").connect_info(info).send().await;
+        assert_eq!(res.into_text().await.unwrap(), "198.51.100.1:443");
+
+        let res = client
+            .get("/")
+            .remote_addr(([127, 0, 0, 1], 5000))
+            .send()
+            .await;
+        assert_eq!(
+            res.into_text().await.unwrap(),
+            "127.0.0.1:5000 127.0.0.1:5000 0.0.0.0:0"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Cookie extractors and, with the `secure-cookies` feature, signed or
+//! encrypted cookies.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::cookies::{Cookie, SameSite};
+//! use foton::{Cookies, Foton, Req, Res};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!
+//!     app.get("/theme", |cookies: Cookies| async move {
+//!         let theme = cookies.get("theme").map(|c| c.value().to_string());
+//!         Res::text(theme.unwrap_or_else(|| "light".into()))
+//!     });
+//!
+//!     app.post("/theme", |_: Req| async move {
+//!         let theme = Cookie::build(("theme", "dark"))
+//!             .secure(true)
+//!             .same_site(SameSite::Lax)
+//!             .path("/");
+//!         Res::text("saved").cookie(theme)
+//!     });
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_trait::async_trait;
+use cookie::CookieJar;
+use hyper::header;
+use std::sync::Arc;
+
+#[cfg(feature = "secure-cookies")]
+use crate::FromRef;
+use crate::{FromRequest, Req, Result};
+
+pub use cookie::time;
+pub use cookie::{Cookie, CookieBuilder, Expiration, SameSite};
+
+#[cfg(feature = "secure-cookies")]
+pub use cookie::Key;
+
+/// Cookies sent with the request.
+///
+/// Names and values are percent-decoded, matching the encoding used by
+/// [`Res::cookie`](crate::Res::cookie).
+#[derive(Debug, Clone, Default)]
+pub struct Cookies {
+    jar: CookieJar,
+}
+
+impl Cookies {
+    /// Parse every `Cookie` header of the request; malformed pairs are skipped.
+    pub fn from_req(req: &Req) -> Self {
+        let mut jar = CookieJar::new();
+        for value in req.headers().get_all(header::COOKIE) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for cookie in Cookie::split_parse_encoded(value.to_string()).flatten() {
+                jar.add_original(cookie);
+            }
+        }
+        Self { jar }
+    }
+
+    /// Get a cookie by name.
+    pub fn get(&self, name: &str) -> Option<&Cookie<'static>> {
+        self.jar.get(name)
+    }
+
+    /// Iterate over all cookies.
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie<'static>> {
+        self.jar.iter()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for Cookies
+where
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        Ok(Cookies::from_req(req))
+    }
+}
+
+/// Cookies authenticated with HMAC-SHA256, keyed from application state.
+///
+/// Values stay readable by the client but cannot be changed without the
+/// [`Key`]. Provide the key by using it as state or implementing
+/// `FromRef<YourState> for Key`.
+///
+/// ```rust
+/// use foton::cookies::{Cookie, Key, SignedCookies};
+/// use foton::{Foton, Res};
+///
+/// let mut app = Foton::with_state(Key::generate());
+/// app.post("/login", |jar: SignedCookies| async move {
+///     let session = Cookie::build(("session", "user-42")).http_only(true).path("/");
+///     Res::text("welcome").cookie(jar.sign(session))
+/// });
+/// ```
+#[cfg(feature = "secure-cookies")]
+pub struct SignedCookies {
+    cookies: Cookies,
+    key: Key,
+}
+
+#[cfg(feature = "secure-cookies")]
+impl SignedCookies {
+    /// Get a cookie by name if its signature is valid, with the signature removed.
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        self.cookies.jar.signed(&self.key).get(name)
+    }
+
+    /// Sign a cookie for [`Res::cookie`](crate::Res::cookie).
+    pub fn sign(&self, cookie: impl Into<Cookie<'static>>) -> Cookie<'static> {
+        let cookie = cookie.into();
+        let name = cookie.name().to_string();
+        let mut jar = CookieJar::new();
+        jar.signed_mut(&self.key).add(cookie);
+        jar.get(&name)
+            .cloned()
+            .expect("signed cookie was just added")
+    }
+
+    /// Cookies as sent, without verification.
+    pub fn unverified(&self) -> &Cookies {
+        &self.cookies
+    }
+}
+
+#[cfg(feature = "secure-cookies")]
+#[async_trait]
+impl<S> FromRequest<S> for SignedCookies
+where
+    Key: FromRef<S>,
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(req: &mut Req, state: &Arc<S>) -> Result<Self> {
+        Ok(SignedCookies {
+            cookies: Cookies::from_req(req),
+            key: Key::from_ref(state),
+        })
+    }
+}
+
+/// Cookies encrypted with AES-256-GCM, keyed from application state.
+///
+/// Values are confidential and tamper-proof. The key is taken from state as
+/// for [`SignedCookies`].
+#[cfg(feature = "secure-cookies")]
+pub struct PrivateCookies {
+    cookies: Cookies,
+    key: Key,
+}
+
+#[cfg(feature = "secure-cookies")]
+impl PrivateCookies {
+    /// Get a cookie by name if it decrypts, with its plaintext value.
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        self.cookies.jar.private(&self.key).get(name)
+    }
+
+    /// Encrypt a cookie for [`Res::cookie`](crate::Res::cookie).
+    pub fn encrypt(&self, cookie: impl Into<Cookie<'static>>) -> Cookie<'static> {
+        let cookie = cookie.into();
+        let name = cookie.name().to_string();
+        let mut jar = CookieJar::new();
+        jar.private_mut(&self.key).add(cookie);
+        jar.get(&name)
+            .cloned()
+            .expect("encrypted cookie was just added")
+    }
+}
+
+#[cfg(feature = "secure-cookies")]
+#[async_trait]
+impl<S> FromRequest<S> for PrivateCookies
+where
+    Key: FromRef<S>,
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(req: &mut Req, state: &Arc<S>) -> Result<Self> {
+        Ok(PrivateCookies {
+            cookies: Cookies::from_req(req),
+            key: Key::from_ref(state),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Foton, Res};
+
+    #[tokio::test]
+    async fn test_cookies_round_trip() {
+        let mut app = Foton::new();
+        app.get("/", |cookies: Cookies| async move {
+            let mut names: Vec<_> = cookies
+                .iter()
+                .map(|c| format!("{}={}", c.name(), c.value()))
+                .collect();
+            names.sort();
+            Res::text(names.join(","))
+                .cookie(
+                    Cookie::build(("greeting", "hello world"))
+                        .path("/")
+                        .http_only(true)
+                        .secure(true)
+                        .same_site(SameSite::Strict)
+                        .max_age(time::Duration::hours(1))
+                        .partitioned(true),
+                )
+                .remove_cookie(Cookie::build("old").path("/"))
+        });
+        let client = app.into_test_client();
+
+        let res = client
+            .get("/")
+            .header("cookie", "a=1; b=two%20words")
+            .header("cookie", "c=3; broken")
+            .send()
+            .await;
+
+        let set_cookie: Vec<_> = res
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(set_cookie.len(), 2);
+        assert!(set_cookie[0].starts_with("greeting=hello%20world;"));
+        for attr in [
+            "HttpOnly",
+            "SameSite=Strict",
+            "Secure",
+            "Partitioned",
+            "Path=/",
+            "Max-Age=3600",
+        ] {
+            assert!(set_cookie[0].contains(attr), "missing {}", attr);
+        }
+        assert!(set_cookie[1].starts_with("old=;"));
+        assert!(set_cookie[1].contains("Max-Age=0"));
+        assert_eq!(res.into_text().await.unwrap(), "a=1,b=two words,c=3");
+    }
+
+    #[cfg(feature = "secure-cookies")]
+    #[tokio::test]
+    async fn test_signed_and_private_cookies() {
+        let key = Key::generate();
+        let mut app = Foton::with_state(key.clone());
+        app.get(
+            "/set",
+            |signed: SignedCookies, private: PrivateCookies| async move {
+                Res::text("ok")
+                    .cookie(signed.sign(Cookie::new("user", "42")))
+                    .cookie(private.encrypt(Cookie::new("secret", "s3cr3t")))
+            },
+        );
+        app.get(
+            "/get",
+            |signed: SignedCookies, private: PrivateCookies| async move {
+                let user = signed.get("user").map(|c| c.value().to_string());
+                let secret = private.get("secret").map(|c| c.value().to_string());
+                format!("{:?} {:?}", user, secret)
+            },
+        );
+        let client = app.into_test_client();
+
+        let res = client.get("/set").send().await;
+        let cookies: Vec<_> = res
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect();
+        assert!(!cookies[1].contains("s3cr3t"));
+        let header = cookies.join("; ");
+
+        let res = client.get("/get").header("cookie", &header).send().await;
+        assert_eq!(
+            res.into_text().await.unwrap(),
+            "Some(\"42\") Some(\"s3cr3t\")"
+        );
+
+        // Tampered values are rejected
+        let signed = cookies[0].split(';').next().unwrap();
+        let tampered = format!("{}43", signed.strip_suffix("42").unwrap());
+        let private = cookies[1].split(';').next().unwrap();
+        let res = client
+            .get("/get")
+            .header("cookie", format!("{}; {}", tampered, private))
+            .send()
+            .await;
+        assert_eq!(res.into_text().await.unwrap(), "None Some(\"s3cr3t\")");
+    }
+}
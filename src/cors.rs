@@ -0,0 +1,506 @@
+//! Cross-Origin Resource Sharing middleware.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::{Cors, Foton, Req, Res};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!     app.attach(
+//!         Cors::new()
+//!             .allow_origin("https://app.example.com")
+//!             .allow_origin_fn(|origin| origin.ends_with(".example.dev"))
+//!             .allow_headers(["content-type", "authorization"])
+//!             .expose_headers(["x-request-id"])
+//!             .allow_credentials(true)
+//!             .max_age(Duration::from_secs(600)),
+//!     );
+//!     app.post("/items", |_: Req| async { Res::text("created") });
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_trait::async_trait;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, StatusCode};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::UnmatchedPath;
+use crate::res::append_vary;
+use crate::{Middleware, Next, Req, Res};
+
+type OriginPredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Rule matching the `Origin` request header.
+#[derive(Clone)]
+enum OriginRule {
+    Exact(String),
+    Predicate(OriginPredicate),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl OriginRule {
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            OriginRule::Exact(allowed) => allowed.eq_ignore_ascii_case(origin),
+            OriginRule::Predicate(predicate) => predicate(origin),
+            #[cfg(feature = "regex")]
+            OriginRule::Regex(regex) => regex.is_match(origin),
+        }
+    }
+}
+
+/// Middleware implementing CORS.
+///
+/// Preflight requests (`OPTIONS` with `Origin` and
+/// `Access-Control-Request-Method`) are answered with `204 No Content` without
+/// reaching the handler, for any registered path. Other requests from an
+/// allowed origin get `Access-Control-Allow-Origin` and related headers added
+/// to the response. Requests from other origins pass through undecorated, so
+/// the browser blocks them.
+#[derive(Clone)]
+pub struct Cors {
+    any_origin: bool,
+    origins: Vec<OriginRule>,
+    methods: Vec<Method>,
+    any_method: bool,
+    any_header: bool,
+    allow_headers: Vec<HeaderName>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            any_origin: false,
+            origins: Vec::new(),
+            methods: vec![
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ],
+            any_method: false,
+            any_header: false,
+            allow_headers: Vec::new(),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl fmt::Debug for Cors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cors")
+            .field("any_origin", &self.any_origin)
+            .field("origins", &self.origins.len())
+            .field("methods", &self.methods)
+            .field("any_method", &self.any_method)
+            .field("any_header", &self.any_header)
+            .field("allow_headers", &self.allow_headers)
+            .field("expose_headers", &self.expose_headers)
+            .field("credentials", &self.credentials)
+            .field("max_age", &self.max_age)
+            .finish()
+    }
+}
+
+impl Cors {
+    /// Allow no origins yet; the common methods and no extra headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow any origin, method and request header.
+    pub fn permissive() -> Self {
+        Self::new()
+            .allow_any_origin()
+            .allow_any_method()
+            .allow_any_header()
+    }
+
+    /// Allow an exact origin, such as `https://example.com`.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins.push(OriginRule::Exact(origin.into()));
+        self
+    }
+
+    /// Allow each of the given exact origins.
+    pub fn allow_origins<I, O>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = O>,
+        O: Into<String>,
+    {
+        self.origins
+            .extend(origins.into_iter().map(|o| OriginRule::Exact(o.into())));
+        self
+    }
+
+    /// Allow origins accepted by `predicate`.
+    pub fn allow_origin_fn<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.origins
+            .push(OriginRule::Predicate(Arc::new(predicate)));
+        self
+    }
+
+    /// Allow origins matching `regex`. Anchor it with `^...$` to match whole origins.
+    ///
+    /// Requires the `regex` feature.
+    #[cfg(feature = "regex")]
+    pub fn allow_origin_regex(mut self, regex: regex::Regex) -> Self {
+        self.origins.push(OriginRule::Regex(regex));
+        self
+    }
+
+    /// Allow every origin.
+    ///
+    /// With credentials enabled the request origin is echoed instead of `*`.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.any_origin = true;
+        self
+    }
+
+    /// Replace the allowed methods.
+    pub fn allow_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Allow whatever method the preflight asks for.
+    pub fn allow_any_method(mut self) -> Self {
+        self.any_method = true;
+        self
+    }
+
+    /// Allow the given request headers. Invalid names are ignored.
+    pub fn allow_headers<I, H>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = H>,
+        H: AsRef<str>,
+    {
+        self.allow_headers.extend(parse_names(headers));
+        self
+    }
+
+    /// Allow whatever headers the preflight asks for.
+    pub fn allow_any_header(mut self) -> Self {
+        self.any_header = true;
+        self
+    }
+
+    /// Let scripts read the given response headers. Invalid names are ignored.
+    pub fn expose_headers<I, H>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = H>,
+        H: AsRef<str>,
+    {
+        self.expose_headers.extend(parse_names(headers));
+        self
+    }
+
+    /// Allow cookies and `Authorization` on cross-origin requests.
+    pub fn allow_credentials(mut self, enabled: bool) -> Self {
+        self.credentials = enabled;
+        self
+    }
+
+    /// Let browsers cache preflight results for `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn is_allowed(&self, origin: &str) -> bool {
+        self.any_origin || self.origins.iter().any(|rule| rule.matches(origin))
+    }
+
+    /// Whether the allow-origin header depends on the request origin.
+    fn varies_by_origin(&self) -> bool {
+        !self.any_origin || self.credentials
+    }
+
+    /// Insert `Access-Control-Allow-Origin` and `-Credentials`.
+    fn allow_origin_headers(&self, headers: &mut HeaderMap, origin: &HeaderValue) {
+        let value = if self.varies_by_origin() {
+            origin.clone()
+        } else {
+            HeaderValue::from_static("*")
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    fn preflight(&self, req: &Req, origin: Option<&HeaderValue>) -> Res {
+        let mut res = Res::status(StatusCode::NO_CONTENT.as_u16());
+        let headers = res.headers_mut();
+
+        if self.varies_by_origin() {
+            append_vary(headers, "origin");
+        }
+        append_vary(headers, "access-control-request-method");
+        append_vary(headers, "access-control-request-headers");
+
+        let Some(origin) = origin else {
+            return res;
+        };
+        self.allow_origin_headers(headers, origin);
+
+        let allow_methods = if self.any_method {
+            req.headers()
+                .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+                .cloned()
+        } else {
+            join(self.methods.iter().map(Method::as_str))
+        };
+        if let Some(allow_methods) = allow_methods {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, allow_methods);
+        }
+
+        let allow_headers = if self.any_header {
+            req.headers()
+                .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+                .cloned()
+        } else {
+            join(self.allow_headers.iter().map(HeaderName::as_str))
+        };
+        if let Some(allow_headers) = allow_headers {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+
+        if let Some(max_age) = self.max_age {
+            headers.insert(
+                header::ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from(max_age.as_secs()),
+            );
+        }
+
+        res
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for Cors
+where
+    S: Send + Sync + 'static,
+{
+    async fn handle(&self, req: Req, _state: Arc<S>, next: Next<S>) -> Res {
+        let origin = req.headers().get(header::ORIGIN).cloned();
+        let allowed = origin
+            .as_ref()
+            .and_then(|o| o.to_str().ok())
+            .is_some_and(|o| self.is_allowed(o));
+        let origin = origin.filter(|_| allowed);
+
+        let is_preflight = req.method() == Method::OPTIONS
+            && req.extensions().get::<UnmatchedPath>().is_none()
+            && req.headers().contains_key(header::ORIGIN)
+            && req
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+        if is_preflight {
+            return self.preflight(&req, origin.as_ref());
+        }
+
+        let mut res = next.run(req).await;
+        let headers = res.headers_mut();
+
+        if self.varies_by_origin() {
+            append_vary(headers, "origin");
+        }
+
+        if let Some(origin) = origin {
+            self.allow_origin_headers(headers, &origin);
+            if let Some(expose) = join(self.expose_headers.iter().map(HeaderName::as_str)) {
+                headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose);
+            }
+        }
+
+        res
+    }
+}
+
+fn parse_names<I, H>(names: I) -> impl Iterator<Item = HeaderName>
+where
+    I: IntoIterator<Item = H>,
+    H: AsRef<str>,
+{
+    names
+        .into_iter()
+        .filter_map(|name| HeaderName::from_bytes(name.as_ref().as_bytes()).ok())
+}
+
+/// Join values into a comma-separated header, or `None` when empty.
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> Option<HeaderValue> {
+    let joined = values.collect::<Vec<_>>().join(", ");
+    if joined.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&joined).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Foton;
+
+    fn app(cors: Cors) -> Foton {
+        let mut app = Foton::new();
+        app.attach(cors);
+        app.get("/items", |_: Req| async { Res::text("list") });
+        app.delete("/items/{id}", |_: Req| async { Res::text("deleted") });
+        app
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let cors = Cors::new()
+            .allow_origin("https://a.example")
+            .allow_headers(["content-type", "x-token"])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(600));
+        let client = app(cors).into_test_client();
+
+        // DELETE is registered, but OPTIONS is not: no 405
+        let res = client
+            .request(Method::OPTIONS, "/items/7")
+            .header("origin", "https://a.example")
+            .header("access-control-request-method", "DELETE")
+            .header("access-control-request-headers", "x-token")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 204);
+        let headers = res.headers();
+        assert_eq!(headers["access-control-allow-origin"], "https://a.example");
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(
+            headers["access-control-allow-methods"],
+            "GET, HEAD, POST, PUT, PATCH, DELETE"
+        );
+        assert_eq!(
+            headers["access-control-allow-headers"],
+            "content-type, x-token"
+        );
+        assert_eq!(headers["access-control-max-age"], "600");
+
+        // Disallowed origin gets no CORS headers
+        let res = client
+            .request(Method::OPTIONS, "/items/7")
+            .header("origin", "https://evil.example")
+            .header("access-control-request-method", "DELETE")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 204);
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+
+        // Plain OPTIONS is still a 405, unknown paths still 404
+        let res = client.request(Method::OPTIONS, "/items/7").send().await;
+        assert_eq!(res.status_code(), 405);
+        let res = client
+            .request(Method::OPTIONS, "/missing")
+            .header("origin", "https://a.example")
+            .header("access-control-request-method", "GET")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 404);
+
+        // Global CORS also covers 404s, so scripts can read them
+        let res = client
+            .get("/missing")
+            .header("origin", "https://a.example")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 404);
+        assert_eq!(
+            res.headers()["access-control-allow-origin"],
+            "https://a.example"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_actual_request() {
+        let cors = Cors::new()
+            .allow_origin_fn(|origin| origin.ends_with(".example"))
+            .expose_headers(["x-total"]);
+        let client = app(cors).into_test_client();
+
+        let res = client
+            .get("/items")
+            .header("origin", "https://b.example")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(
+            res.headers()["access-control-allow-origin"],
+            "https://b.example"
+        );
+        assert_eq!(res.headers()["access-control-expose-headers"], "x-total");
+        assert_eq!(res.headers()["vary"], "origin");
+        assert_eq!(res.into_text().await.unwrap(), "list");
+
+        let res = client
+            .get("/items")
+            .header("origin", "https://other.test")
+            .send()
+            .await;
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+        assert_eq!(res.headers()["vary"], "origin");
+    }
+
+    #[tokio::test]
+    async fn test_permissive() {
+        let client = app(Cors::permissive()).into_test_client();
+
+        let res = client
+            .request(Method::OPTIONS, "/items")
+            .header("origin", "https://any.test")
+            .header("access-control-request-method", "PROPFIND")
+            .header("access-control-request-headers", "x-custom")
+            .send()
+            .await;
+        assert_eq!(res.headers()["access-control-allow-origin"], "*");
+        assert_eq!(res.headers()["access-control-allow-methods"], "PROPFIND");
+        assert_eq!(res.headers()["access-control-allow-headers"], "x-custom");
+
+        let res = client
+            .get("/items")
+            .header("origin", "https://any.test")
+            .send()
+            .await;
+        assert_eq!(res.headers()["access-control-allow-origin"], "*");
+        assert!(res.headers().get("vary").is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_origin_regex() {
+        let cors = Cors::new()
+            .allow_origin_regex(regex::Regex::new(r"^https://([a-z]+\.)?example\.com$").unwrap());
+        assert!(cors.is_allowed("https://example.com"));
+        assert!(cors.is_allowed("https://api.example.com"));
+        assert!(!cors.is_allowed("https://example.com.evil.test"));
+    }
+}
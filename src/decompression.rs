@@ -0,0 +1,168 @@
+//! Request body decompression.
+//!
+//! Enabled with the `compression` feature flag. Bodies sent with a
+//! `Content-Encoding` of gzip, deflate, br or zstd are decoded as they are
+//! read, so every extractor sees plain data and `body_limit` bounds the
+//! decompressed size.
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder, ZstdDecoder};
+use futures_util::TryStreamExt;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+use hyper::header::{self, HeaderMap};
+use tokio::io::AsyncBufRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::Error;
+use crate::req::ReqBody;
+
+type Reader = Box<dyn AsyncBufRead + Send + Sync + Unpin>;
+
+/// Wrap `body` in decoders for its `Content-Encoding`.
+///
+/// The `Content-Encoding` and `Content-Length` headers are removed once the
+/// body is decoded. Unsupported codings leave the headers in place and make
+/// reading the body fail with 415 Unsupported Media Type.
+pub(crate) fn decode_body(headers: &mut HeaderMap, body: ReqBody) -> ReqBody {
+    let codings: Vec<String> = headers
+        .get_all(header::CONTENT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|c| c.trim().to_ascii_lowercase())
+        .filter(|c| !c.is_empty() && c != "identity")
+        .collect();
+
+    if codings.is_empty() {
+        return body;
+    }
+
+    if let Some(unsupported) = codings
+        .iter()
+        .find(|c| !matches!(c.as_str(), "gzip" | "x-gzip" | "deflate" | "br" | "zstd"))
+    {
+        let error =
+            Error::unsupported_media_type(format!("Unsupported Content-Encoding: {}", unsupported));
+        let stream = futures_util::stream::iter([Err(error)]);
+        return BodyExt::boxed(StreamBody::new(stream));
+    }
+
+    headers.remove(header::CONTENT_ENCODING);
+    headers.remove(header::CONTENT_LENGTH);
+
+    let mut reader: Reader = Box::new(StreamReader::new(
+        body.into_data_stream().map_err(std::io::Error::other),
+    ));
+
+    // Codings are listed in the order they were applied
+    for coding in codings.iter().rev() {
+        reader = match coding.as_str() {
+            "gzip" | "x-gzip" => Box::new(tokio::io::BufReader::new(GzipDecoder::new(reader))),
+            "deflate" => Box::new(tokio::io::BufReader::new(ZlibDecoder::new(reader))),
+            "br" => Box::new(tokio::io::BufReader::new(BrotliDecoder::new(reader))),
+            _ => Box::new(tokio::io::BufReader::new(ZstdDecoder::new(reader))),
+        };
+    }
+
+    let stream = ReaderStream::new(reader)
+        .map_ok(Frame::data)
+        .map_err(decode_error);
+    BodyExt::boxed(StreamBody::new(stream))
+}
+
+/// Recover body errors passed through the decoder, report the rest as 400.
+fn decode_error(error: std::io::Error) -> Error {
+    if error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+        if let Some(inner) = error.into_inner() {
+            if let Ok(error) = inner.downcast::<Error>() {
+                return *error;
+            }
+        }
+        return Error::internal("Failed to read body");
+    }
+    Error::bad_request(format!("Failed to decode request body: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::extractors::Json;
+    use crate::{Foton, Req};
+    use async_compression::tokio::write::{BrotliEncoder, GzipEncoder, ZlibEncoder};
+    use tokio::io::AsyncWriteExt;
+
+    async fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(data).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        encoder.into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_decodes_request_bodies() {
+        let mut app = Foton::new();
+        app.post("/json", |Json(value): Json<serde_json::Value>| async move {
+            value["name"].as_str().unwrap_or_default().to_string()
+        });
+        let client = app.into_test_client();
+
+        let json = br#"{"name":"mobile"}"#;
+        let mut deflated = ZlibEncoder::new(Vec::new());
+        deflated.write_all(json).await.unwrap();
+        deflated.shutdown().await.unwrap();
+        let mut brotli = BrotliEncoder::new(Vec::new());
+        brotli.write_all(json).await.unwrap();
+        brotli.shutdown().await.unwrap();
+
+        for (coding, body) in [
+            ("gzip", gzip(json).await),
+            ("deflate", deflated.into_inner()),
+            ("br", brotli.into_inner()),
+        ] {
+            let res = client
+                .post("/json")
+                .header("content-type", "application/json")
+                .header("content-encoding", coding)
+                .body(body)
+                .send()
+                .await;
+            assert_eq!(res.status_code(), 200, "{}", coding);
+            assert_eq!(res.into_text().await.unwrap(), "mobile");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decompressed_size_is_limited() {
+        let mut app = Foton::new();
+        app.set_body_limit(4096);
+        app.post("/", |mut req: Req| async move {
+            req.body().await.map(|body| body.len().to_string())
+        });
+        let client = app.into_test_client();
+
+        let bomb = gzip(&vec![0u8; 1024 * 1024]).await;
+        assert!(bomb.len() < 4096);
+        let res = client
+            .post("/")
+            .header("content-encoding", "gzip")
+            .body(bomb)
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 413);
+
+        let res = client
+            .post("/")
+            .header("content-encoding", "gzip")
+            .body("not gzip")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 400);
+
+        let res = client
+            .post("/")
+            .header("content-encoding", "compress")
+            .body("data")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 415);
+    }
+}
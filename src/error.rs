@@ -6,7 +6,10 @@ use std::fmt;
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// HTTP error.
+///
+/// New variants may be added in minor releases, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// HTTP status with optional message.
     Status(u16, Option<String>),
@@ -18,6 +21,8 @@ pub enum Error {
     Io(std::io::Error),
     /// Custom error.
     Custom(String),
+    /// Invalid route table.
+    Route(RouteError),
 }
 
 impl Error {
@@ -46,16 +51,31 @@ impl Error {
         Self::Status(405, Some(msg.into()))
     }
 
+    /// Create 408 Request Timeout.
+    pub fn request_timeout(msg: impl Into<String>) -> Self {
+        Self::Status(408, Some(msg.into()))
+    }
+
     /// Create 413 Payload Too Large.
     pub fn payload_too_large(msg: impl Into<String>) -> Self {
         Self::Status(413, Some(msg.into()))
     }
 
+    /// Create 415 Unsupported Media Type.
+    pub fn unsupported_media_type(msg: impl Into<String>) -> Self {
+        Self::Status(415, Some(msg.into()))
+    }
+
     /// Create 422 Unprocessable Entity.
     pub fn unprocessable(msg: impl Into<String>) -> Self {
         Self::Status(422, Some(msg.into()))
     }
 
+    /// Create 429 Too Many Requests.
+    pub fn too_many_requests(msg: impl Into<String>) -> Self {
+        Self::Status(429, Some(msg.into()))
+    }
+
     /// Create 500 Internal Server Error.
     pub fn internal(msg: impl Into<String>) -> Self {
         Self::Status(500, Some(msg.into()))
@@ -65,6 +85,28 @@ impl Error {
     pub fn status(code: u16) -> Self {
         Self::Status(code, None)
     }
+
+    /// HTTP status code for this error.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Error::Status(code, _) => *code,
+            Error::Json(_) => 400,
+            Error::Hyper(_) | Error::Io(_) | Error::Custom(_) | Error::Route(_) => 500,
+        }
+    }
+
+    /// Error message without the status prefix.
+    pub fn message(&self) -> String {
+        match self {
+            Error::Status(_, Some(msg)) => msg.clone(),
+            Error::Status(code, None) => hyper::StatusCode::from_u16(*code)
+                .ok()
+                .and_then(|s| s.canonical_reason())
+                .unwrap_or("Unknown error")
+                .to_string(),
+            other => other.to_string(),
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -76,6 +118,7 @@ impl fmt::Display for Error {
             Error::Hyper(e) => write!(f, "HTTP error: {}", e),
             Error::Io(e) => write!(f, "IO error: {}", e),
             Error::Custom(msg) => write!(f, "{}", msg),
+            Error::Route(e) => write!(f, "{}", e),
         }
     }
 }
@@ -94,6 +137,18 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<std::convert::Infallible> for Error {
+    fn from(err: std::convert::Infallible) -> Self {
+        match err {}
+    }
+}
+
+impl From<RouteError> for Error {
+    fn from(err: RouteError) -> Self {
+        Error::Route(err)
+    }
+}
+
 impl From<String> for Error {
     fn from(msg: String) -> Self {
         Error::Custom(msg)
@@ -105,3 +160,77 @@ impl From<&str> for Error {
         Error::Custom(msg.to_string())
     }
 }
+
+/// Problem found in a single route while building the route table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteIssue {
+    /// Two patterns would match the same requests.
+    Conflict {
+        /// Pattern that failed to register.
+        path: String,
+        /// Previously registered pattern it overlaps with.
+        existing: String,
+    },
+    /// Pattern that cannot be parsed.
+    Invalid {
+        /// Offending pattern.
+        path: String,
+        /// Why the pattern was rejected.
+        reason: String,
+    },
+    /// The same method and path registered more than once.
+    Duplicate {
+        /// Registered method.
+        method: hyper::Method,
+        /// Registered pattern.
+        path: String,
+    },
+}
+
+impl fmt::Display for RouteIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteIssue::Conflict { path, existing } => {
+                write!(f, "route {} conflicts with {}", path, existing)
+            }
+            RouteIssue::Invalid { path, reason } => {
+                write!(f, "invalid route {}: {}", path, reason)
+            }
+            RouteIssue::Duplicate { method, path } => {
+                write!(f, "{} {} is registered more than once", method, path)
+            }
+        }
+    }
+}
+
+/// Every problem found while building the route table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteError {
+    issues: Vec<RouteIssue>,
+}
+
+impl RouteError {
+    pub(crate) fn new(issues: Vec<RouteIssue>) -> Self {
+        Self { issues }
+    }
+
+    /// Issues in registration order.
+    pub fn issues(&self) -> &[RouteIssue] {
+        &self.issues
+    }
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid routes: ")?;
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RouteError {}
@@ -1,9 +1,71 @@
 //! Error handler trait.
 
+use hyper::header;
+use std::sync::Arc;
+
 use crate::{Error, Res};
 
 /// Convert errors to HTTP responses.
+///
+/// The configured handler renders every error the framework produces:
+/// extractor failures, unmatched routes and methods, handler timeouts and
+/// `Err` values returned from handlers or middleware.
+///
+/// ```rust
+/// use foton::{Error, ErrorHandler, Foton, Res};
+///
+/// struct JsonErrors;
+///
+/// impl ErrorHandler for JsonErrors {
+///     fn handle(&self, error: Error) -> Res {
+///         Res::builder()
+///             .status(error.status_code())
+///             .json(&serde_json::json!({ "error": error.message() }))
+///     }
+/// }
+///
+/// let mut app = Foton::new();
+/// app.set_error_handler(JsonErrors);
+/// ```
 pub trait ErrorHandler: Send + Sync + 'static {
     /// Handle error and return response.
     fn handle(&self, error: Error) -> Res;
 }
+
+impl<F> ErrorHandler for F
+where
+    F: Fn(Error) -> Res + Send + Sync + 'static,
+{
+    fn handle(&self, error: Error) -> Res {
+        self(error)
+    }
+}
+
+/// Re-render a response produced from an `Error` through the handler.
+///
+/// Headers already on the response, such as those added by middleware, are
+/// kept unless the handler sets them itself. The body headers of the default
+/// rendering are dropped.
+pub(crate) fn render(handler: Option<&Arc<dyn ErrorHandler>>, mut res: Res) -> Res {
+    match (handler, res.take_error()) {
+        (Some(handler), Some(error)) => {
+            let mut rendered = handler.handle(error);
+            rendered.take_error();
+
+            let headers = res.headers();
+            for name in headers.keys() {
+                if name == header::CONTENT_TYPE
+                    || name == header::CONTENT_LENGTH
+                    || rendered.headers().contains_key(name)
+                {
+                    continue;
+                }
+                for value in headers.get_all(name) {
+                    rendered.headers_mut().append(name, value.clone());
+                }
+            }
+            rendered
+        }
+        _ => res,
+    }
+}
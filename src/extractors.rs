@@ -1,10 +1,13 @@
 //! Type-safe request extractors.
 
-use crate::{Error, Req, Result};
+use crate::{BodyStream, Error, Req, Result};
 use async_trait::async_trait;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_util::StreamExt;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
 use serde::de::DeserializeOwned;
-use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
 
 /// Extract data from request.
 #[async_trait]
@@ -14,20 +17,86 @@ pub trait FromRequest<S = ()>: Sized {
 }
 
 /// Application state extractor.
-pub struct State<S>(pub S);
+///
+/// Extracts the whole state when it is `Clone`, or any part of it that
+/// implements [`FromRef`]. Use [`StateRef`] to share non-`Clone` state.
+pub struct State<T>(pub T);
 
 #[async_trait]
-impl<S> FromRequest<S> for State<S>
+impl<S, T> FromRequest<S> for State<T>
 where
-    S: Clone + Send + Sync + 'static,
+    T: FromRef<S>,
+    S: Send + Sync + 'static,
 {
     #[inline]
     async fn from_request(_req: &mut Req, state: &Arc<S>) -> Result<Self> {
-        Ok(State((**state).clone()))
+        Ok(State(T::from_ref(state)))
+    }
+}
+
+/// Derive a piece of application state, such as a pool or config, from the whole.
+///
+/// ```rust
+/// use foton::{Foton, FromRef, State};
+///
+/// #[derive(Clone)]
+/// struct Pool;
+///
+/// struct AppState {
+///     pool: Pool,
+/// }
+///
+/// impl FromRef<AppState> for Pool {
+///     fn from_ref(state: &AppState) -> Self {
+///         state.pool.clone()
+///     }
+/// }
+///
+/// let mut app = Foton::with_state(AppState { pool: Pool });
+/// app.get("/", |State(_pool): State<Pool>| async { "ok" });
+/// ```
+pub trait FromRef<S> {
+    /// Build `Self` from a reference to the state.
+    fn from_ref(state: &S) -> Self;
+}
+
+impl<T: Clone> FromRef<T> for T {
+    #[inline]
+    fn from_ref(state: &T) -> Self {
+        state.clone()
+    }
+}
+
+/// Shared application state extractor.
+///
+/// Hands out the `Arc<S>` created by [`Foton::with_state`](crate::Foton::with_state),
+/// so `S` does not need to be `Clone`.
+pub struct StateRef<S>(pub Arc<S>);
+
+impl<S> std::ops::Deref for StateRef<S> {
+    type Target = S;
+
+    #[inline]
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for StateRef<S>
+where
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(_req: &mut Req, state: &Arc<S>) -> Result<Self> {
+        Ok(StateRef(Arc::clone(state)))
     }
 }
 
 /// Query parameters extractor.
+///
+/// A missing query string deserializes like an empty one, so structs whose
+/// fields are all optional or defaulted still extract.
 pub struct Query<T>(pub T);
 
 #[async_trait]
@@ -38,10 +107,7 @@ where
 {
     #[inline]
     async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
-        let query = req
-            .uri()
-            .query()
-            .ok_or_else(|| Error::bad_request("Missing query string"))?;
+        let query = req.uri().query().unwrap_or("");
 
         let value = serde_urlencoded::from_str::<T>(query)
             .map_err(|e| Error::bad_request(format!("Invalid query parameters: {}", e)))?;
@@ -108,7 +174,11 @@ where
     }
 }
 
-/// Path parameters extractor (deserializes HashMap directly).
+/// Path parameters extractor.
+///
+/// Deserializes percent-decoded segments into numbers, bools, enums, UUIDs,
+/// tuples (in route order) or structs (by name). Failures respond with 400,
+/// or the status set by [`Foton::set_path_error_status`](crate::Foton::set_path_error_status).
 pub struct Path<T>(pub T);
 
 #[async_trait]
@@ -119,25 +189,80 @@ where
 {
     #[inline]
     async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
-        let params = req.path_params();
-        let value = deserialize_path_params(params).map_err(|e| {
-            Error::bad_request(format!(
-                "Invalid path parameters: {}. Use String type for path segments",
-                e
-            ))
-        })?;
+        let value = crate::path_de::from_params(req.ordered_params())
+            .map_err(|e| Error::Status(req.path_error_status(), Some(e.to_string())))?;
 
         Ok(Path(value))
     }
 }
 
-/// Deserialize HashMap<String, String> directly to T.
-fn deserialize_path_params<T: DeserializeOwned>(
-    params: &HashMap<String, String>,
-) -> std::result::Result<T, serde::de::value::Error> {
-    use serde::de::value::MapDeserializer;
-    let deserializer = MapDeserializer::new(params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
-    T::deserialize(deserializer)
+/// Headers for the response to a rejected extractor, such as a
+/// `WWW-Authenticate` challenge.
+///
+/// Kept in the request extensions beside the returned [`Error`], so the error
+/// itself stays a plain status. Handlers apply them when rendering the
+/// rejection; `Option` and `Result` extractors drop them.
+#[derive(Debug, Default)]
+pub(crate) struct RejectionHeaders(pub(crate) HeaderMap);
+
+/// Reject with `error`, adding `name: value` to the eventual response.
+pub(crate) fn reject_with(req: &mut Req, error: Error, name: HeaderName, value: &str) -> Error {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        match req.extensions_mut().get_mut::<RejectionHeaders>() {
+            Some(RejectionHeaders(headers)) => {
+                headers.append(name, value);
+            }
+            None => {
+                let mut headers = HeaderMap::new();
+                headers.append(name, value);
+                req.extensions_mut().insert(RejectionHeaders(headers));
+            }
+        }
+    }
+    error
+}
+
+/// Optional extractor: client errors such as missing or malformed input
+/// become `None`.
+///
+/// Bodies over the limit (413), slow uploads (408) and server errors are
+/// still returned, so they are not mistaken for absent input.
+#[async_trait]
+impl<E, S> FromRequest<S> for Option<E>
+where
+    E: FromRequest<S> + Send,
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(req: &mut Req, state: &Arc<S>) -> Result<Self> {
+        match E::from_request(req, state).await {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => match e.status_code() {
+                408 | 413 | 500.. => Err(e),
+                _ => {
+                    req.extensions_mut().remove::<RejectionHeaders>();
+                    Ok(None)
+                }
+            },
+        }
+    }
+}
+
+/// Fallible extractor: the rejection is handed to the handler.
+#[async_trait]
+impl<E, S> FromRequest<S> for std::result::Result<E, Error>
+where
+    E: FromRequest<S> + Send,
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(req: &mut Req, state: &Arc<S>) -> Result<Self> {
+        let result = E::from_request(req, state).await;
+        if result.is_err() {
+            req.extensions_mut().remove::<RejectionHeaders>();
+        }
+        Ok(result)
+    }
 }
 
 /// Headers extractor.
@@ -169,10 +294,482 @@ where
     }
 }
 
+#[async_trait]
+impl<S> FromRequest<S> for BodyStream
+where
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        req.body_stream()
+    }
+}
+
+/// Size limits for [`Multipart`] fields.
+///
+/// Insert into request extensions (e.g. from a middleware) to change the limits
+/// for a group of routes. The application `body_limit` always applies too.
+#[derive(Debug, Clone, Copy)]
+pub struct MultipartLimits {
+    field: Option<usize>,
+    file: Option<usize>,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        Self {
+            field: Some(1024 * 1024),
+            file: None,
+        }
+    }
+}
+
+impl MultipartLimits {
+    /// Create default limits: 1 MiB per non-file field, no per-file limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set maximum size of a single non-file field.
+    pub fn field_limit(mut self, limit: usize) -> Self {
+        self.field = Some(limit);
+        self
+    }
+
+    /// Set maximum size of a single file part.
+    pub fn file_limit(mut self, limit: usize) -> Self {
+        self.file = Some(limit);
+        self
+    }
+}
+
+/// Maximum size of the header block of a single part.
+const MULTIPART_HEADER_LIMIT: usize = 8 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MultipartState {
+    Preamble,
+    Boundary,
+    Headers,
+    Body,
+    Done,
+}
+
+/// Multipart form data extractor.
+///
+/// Parses `multipart/form-data` incrementally as the body arrives, so large
+/// file parts can be streamed to disk without buffering the whole request.
+///
+/// ```rust,no_run
+/// use foton::{Multipart, Res, Result};
+///
+/// async fn upload(mut form: Multipart) -> Result<Res> {
+///     while let Some(field) = form.next_field().await? {
+///         if field.file_name().is_some() {
+///             // Never trust the client file name as a path
+///             let path = format!("/tmp/uploads/{}", uuid::Uuid::new_v4());
+///             field.save_to(path).await?;
+///         } else {
+///             let name = field.name().unwrap_or_default().to_string();
+///             println!("{} = {}", name, field.text().await?);
+///         }
+///     }
+///     Ok(Res::text("Uploaded"))
+/// }
+/// ```
+pub struct Multipart {
+    body: BodyStream,
+    delimiter: Bytes,
+    buf: BytesMut,
+    state: MultipartState,
+    limits: MultipartLimits,
+    eof: bool,
+}
+
+impl Multipart {
+    fn new(body: BodyStream, boundary: &str, limits: MultipartLimits) -> Self {
+        let mut delimiter = BytesMut::with_capacity(boundary.len() + 4);
+        delimiter.extend_from_slice(b"\r\n--");
+        delimiter.extend_from_slice(boundary.as_bytes());
+
+        // The first delimiter may start the body without a preceding CRLF
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"\r\n");
+
+        Self {
+            body,
+            delimiter: delimiter.freeze(),
+            buf,
+            state: MultipartState::Preamble,
+            limits,
+            eof: false,
+        }
+    }
+
+    /// Override the field size limits for this request.
+    pub fn with_limits(mut self, limits: MultipartLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Get the next field, or `None` after the closing boundary.
+    ///
+    /// Unread data of the previous field is skipped.
+    pub async fn next_field(&mut self) -> Result<Option<Field<'_>>> {
+        while self.state == MultipartState::Body {
+            self.read_chunk().await?;
+        }
+
+        loop {
+            match self.state {
+                MultipartState::Preamble | MultipartState::Body => {
+                    match find(&self.buf, &self.delimiter) {
+                        Some(idx) => {
+                            self.buf.advance(idx + self.delimiter.len());
+                            self.state = MultipartState::Boundary;
+                        }
+                        None => {
+                            let keep = self.delimiter.len() - 1;
+                            if self.buf.len() > keep {
+                                self.buf.advance(self.buf.len() - keep);
+                            }
+                            self.fill().await?;
+                        }
+                    }
+                }
+                MultipartState::Boundary => {
+                    if self.buf.starts_with(b"--") {
+                        self.state = MultipartState::Done;
+                        return Ok(None);
+                    }
+                    match find(&self.buf, b"\r\n") {
+                        // Transport padding may follow the delimiter
+                        Some(idx) if self.buf[..idx].iter().all(|b| matches!(b, b' ' | b'\t')) => {
+                            self.buf.advance(idx + 2);
+                            self.state = MultipartState::Headers;
+                        }
+                        Some(_) => return Err(Error::bad_request("Malformed multipart boundary")),
+                        None => self.fill().await?,
+                    }
+                }
+                MultipartState::Headers => {
+                    let block = if self.buf.starts_with(b"\r\n") {
+                        self.buf.advance(2);
+                        Bytes::new()
+                    } else if let Some(idx) = find(&self.buf, b"\r\n\r\n") {
+                        if idx > MULTIPART_HEADER_LIMIT {
+                            return Err(Error::bad_request("Multipart headers too large"));
+                        }
+                        let block = self.buf.split_to(idx).freeze();
+                        self.buf.advance(4);
+                        block
+                    } else if self.buf.len() > MULTIPART_HEADER_LIMIT {
+                        return Err(Error::bad_request("Multipart headers too large"));
+                    } else {
+                        self.fill().await?;
+                        continue;
+                    };
+
+                    let headers = parse_part_headers(&block)?;
+                    self.state = MultipartState::Body;
+                    return Ok(Some(Field::new(self, headers)));
+                }
+                MultipartState::Done => return Ok(None),
+            }
+        }
+    }
+
+    /// Read the next chunk of the current field, `None` at its end.
+    async fn read_chunk(&mut self) -> Result<Option<Bytes>> {
+        if self.state != MultipartState::Body {
+            return Ok(None);
+        }
+
+        loop {
+            if let Some(idx) = find(&self.buf, &self.delimiter) {
+                if idx > 0 {
+                    return Ok(Some(self.buf.split_to(idx).freeze()));
+                }
+                self.buf.advance(self.delimiter.len());
+                self.state = MultipartState::Boundary;
+                return Ok(None);
+            }
+
+            // Hold back a possible partial delimiter at the end of the buffer
+            let safe = self.buf.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 {
+                return Ok(Some(self.buf.split_to(safe).freeze()));
+            }
+
+            self.fill().await?;
+        }
+    }
+
+    async fn fill(&mut self) -> Result<()> {
+        if self.eof {
+            return Err(Error::bad_request("Incomplete multipart body"));
+        }
+        match self.body.next().await {
+            Some(chunk) => self.buf.extend_from_slice(&chunk?),
+            None => self.eof = true,
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for Multipart
+where
+    S: Send + Sync + 'static,
+{
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        let content_type = req.content_type().unwrap_or("");
+
+        if !content_type
+            .to_ascii_lowercase()
+            .starts_with("multipart/form-data")
+        {
+            return Err(Error::bad_request(
+                "Content-Type must be multipart/form-data",
+            ));
+        }
+
+        let boundary = header_params(content_type)
+            .into_iter()
+            .find(|(key, _)| key == "boundary")
+            .map(|(_, value)| value)
+            .filter(|b| !b.is_empty() && b.len() <= 70)
+            .ok_or_else(|| Error::bad_request("Missing multipart boundary"))?;
+
+        let limits = req
+            .extensions()
+            .get::<MultipartLimits>()
+            .copied()
+            .unwrap_or_default();
+
+        let body = req.body_stream()?;
+        Ok(Multipart::new(body, &boundary, limits))
+    }
+}
+
+/// A single part of a [`Multipart`] body.
+pub struct Field<'a> {
+    multipart: &'a mut Multipart,
+    headers: HeaderMap,
+    name: Option<String>,
+    file_name: Option<String>,
+    limit: Option<usize>,
+    read: usize,
+}
+
+impl<'a> Field<'a> {
+    fn new(multipart: &'a mut Multipart, headers: HeaderMap) -> Self {
+        let (name, file_name) = headers
+            .get(header::CONTENT_DISPOSITION)
+            .map(|v| {
+                let value = String::from_utf8_lossy(v.as_bytes());
+                let mut name = None;
+                let mut file_name = None;
+                for (key, value) in header_params(&value) {
+                    match key.as_str() {
+                        "name" => name = Some(value),
+                        "filename" => file_name = Some(value),
+                        _ => {}
+                    }
+                }
+                (name, file_name)
+            })
+            .unwrap_or_default();
+
+        let limit = if file_name.is_some() {
+            multipart.limits.file
+        } else {
+            multipart.limits.field
+        };
+
+        Self {
+            multipart,
+            headers,
+            name,
+            file_name,
+            limit,
+            read: 0,
+        }
+    }
+
+    /// Get the form field name.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Get the uploaded file name, if this part is a file.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// Get the part Content-Type.
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    /// Get all part headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Read the next chunk of data, `None` at the end of the field.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>> {
+        let chunk = self.multipart.read_chunk().await?;
+
+        if let Some(chunk) = &chunk {
+            self.read += chunk.len();
+            if let Some(limit) = self.limit {
+                if self.read > limit {
+                    return Err(Error::payload_too_large(format!(
+                        "Multipart field '{}' exceeds limit of {} bytes",
+                        self.name().unwrap_or_default(),
+                        limit
+                    )));
+                }
+            }
+        }
+
+        Ok(chunk)
+    }
+
+    /// Read the whole field into memory.
+    pub async fn bytes(mut self) -> Result<Bytes> {
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = self.chunk().await? {
+            buf.extend_from_slice(&chunk);
+        }
+        Ok(buf.freeze())
+    }
+
+    /// Read the whole field as UTF-8 text.
+    pub async fn text(self) -> Result<String> {
+        let name = self.name.clone().unwrap_or_default();
+        let bytes = self.bytes().await?;
+        String::from_utf8(bytes.into()).map_err(|_| {
+            Error::bad_request(format!("Multipart field '{}' is not valid UTF-8", name))
+        })
+    }
+
+    /// Stream the field into a file, returning the number of bytes written.
+    ///
+    /// The partially written file is removed if the upload fails.
+    pub async fn save_to(mut self, path: impl AsRef<std::path::Path>) -> Result<u64> {
+        let path = path.as_ref();
+        let mut file = tokio::fs::File::create(path).await?;
+
+        let result = async {
+            let mut written = 0u64;
+            while let Some(chunk) = self.chunk().await? {
+                file.write_all(&chunk).await?;
+                written += chunk.len() as u64;
+            }
+            file.flush().await?;
+            Ok::<_, Error>(written)
+        }
+        .await;
+
+        drop(file);
+        if result.is_err() {
+            tokio::fs::remove_file(path).await.ok();
+        }
+        result
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn parse_part_headers(block: &[u8]) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    if block.is_empty() {
+        return Ok(headers);
+    }
+
+    for line in block.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let colon = line
+            .iter()
+            .position(|b| *b == b':')
+            .ok_or_else(|| Error::bad_request("Malformed multipart headers"))?;
+
+        let name = HeaderName::from_bytes(line[..colon].trim_ascii())
+            .map_err(|_| Error::bad_request("Malformed multipart headers"))?;
+        let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii())
+            .map_err(|_| Error::bad_request("Malformed multipart headers"))?;
+        headers.append(name, value);
+    }
+
+    Ok(headers)
+}
+
+/// Parse `; key=value` parameters following the first item of a header value.
+///
+/// Keys are lowercased; quoted values are unquoted.
+fn header_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let Some(start) = value.find(';') else {
+        return params;
+    };
+    let mut rest = &value[start..];
+
+    loop {
+        rest = rest.trim_start_matches([';', ' ', '\t']);
+        if rest.is_empty() {
+            break;
+        }
+
+        let end = rest.find(['=', ';']).unwrap_or(rest.len());
+        if !rest[end..].starts_with('=') {
+            // Parameter without a value
+            rest = &rest[end..];
+            continue;
+        }
+        let key = rest[..end].trim().to_ascii_lowercase();
+        rest = rest[end + 1..].trim_start();
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices().peekable();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    '\\' if matches!(chars.peek(), Some((_, '"' | '\\'))) => {
+                        value.push(chars.next().unwrap().1);
+                    }
+                    c => value.push(c),
+                }
+            }
+            rest = &quoted[end..];
+            value
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_string();
+            rest = &rest[end..];
+            value
+        };
+
+        params.push((key, value));
+    }
+
+    params
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
+    use crate::test::TempPath;
 
     #[test]
     fn test_path_deserialize() {
@@ -182,11 +779,12 @@ mod tests {
             name: String,
         }
 
-        let mut map = HashMap::new();
-        map.insert("id".to_string(), "123".to_string());
-        map.insert("name".to_string(), "alice".to_string());
+        let params = vec![
+            ("id".to_string(), "123".to_string()),
+            ("name".to_string(), "alice".to_string()),
+        ];
 
-        let result: Params = deserialize_path_params(&map).unwrap();
+        let result: Params = crate::path_de::from_params(&params).unwrap();
         assert_eq!(result.id, "123");
         assert_eq!(result.name, "alice");
     }
@@ -198,10 +796,341 @@ mod tests {
             id: String,
         }
 
-        let mut map = HashMap::new();
-        map.insert("id".to_string(), "456".to_string());
+        let params = vec![("id".to_string(), "456".to_string())];
 
-        let result: Params = deserialize_path_params(&map).unwrap();
+        let result: Params = crate::path_de::from_params(&params).unwrap();
         assert_eq!(result.id, "456");
     }
+
+    #[test]
+    fn test_path_deserialize_typed_numbers() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Params {
+            id: u64,
+        }
+
+        let params = vec![("id".to_string(), "456".to_string())];
+
+        let result: Params = crate::path_de::from_params(&params).unwrap();
+        assert_eq!(result.id, 456);
+    }
+
+    #[tokio::test]
+    async fn test_path_extractor() {
+        use crate::Foton;
+
+        let mut app = Foton::new();
+        app.get(
+            "/orders/{id}/items/{n}",
+            |Path((id, n)): Path<(uuid::Uuid, i32)>| async move { format!("{} {}", id, n) },
+        );
+        app.get("/users/{id}", |Path(id): Path<u64>| async move {
+            id.to_string()
+        });
+        let client = app.into_test_client();
+
+        let id = uuid::Uuid::new_v4();
+        let res = client.get(format!("/orders/{}/items/-2", id)).send().await;
+        assert_eq!(res.into_text().await.unwrap(), format!("{} -2", id));
+
+        let res = client.get("/orders/nope/items/1").send().await;
+        assert_eq!(res.status_code(), 400);
+        assert!(
+            res.into_text()
+                .await
+                .unwrap()
+                .contains("Invalid path parameter `id`")
+        );
+
+        let mut app = Foton::new();
+        app.set_path_error_status(404);
+        app.get("/users/{id}", |Path(id): Path<u64>| async move {
+            id.to_string()
+        });
+        let res = app.into_test_client().get("/users/abc").send().await;
+        assert_eq!(res.status_code(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_state_without_clone() {
+        use crate::Foton;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct AppState {
+            hits: AtomicUsize,
+            name: String,
+        }
+
+        #[derive(Clone)]
+        struct Name(String);
+
+        impl FromRef<AppState> for Name {
+            fn from_ref(state: &AppState) -> Self {
+                Name(state.name.clone())
+            }
+        }
+
+        let mut app = Foton::with_state(AppState {
+            hits: AtomicUsize::new(0),
+            name: "foton".to_string(),
+        });
+        app.get(
+            "/",
+            |state: StateRef<AppState>, State(Name(name)): State<Name>| async move {
+                let hits = state.hits.fetch_add(1, Ordering::SeqCst) + 1;
+                format!("{} {}", name, hits)
+            },
+        );
+        let client = app.into_test_client();
+
+        client.get("/").send().await;
+        let res = client.get("/").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "foton 2");
+    }
+
+    #[tokio::test]
+    async fn test_state_ref_with_substates() {
+        use crate::{Foton, Next, Router, from_fn};
+        use std::sync::Mutex;
+
+        // Neither the state nor the config is `Clone`
+        struct Config {
+            greeting: String,
+        }
+
+        struct AppState {
+            config: Arc<Config>,
+            log: Mutex<Vec<String>>,
+        }
+
+        impl FromRef<AppState> for Arc<Config> {
+            fn from_ref(state: &AppState) -> Self {
+                Arc::clone(&state.config)
+            }
+        }
+
+        let mut app = Foton::with_state(AppState {
+            config: Arc::new(Config {
+                greeting: "hello".to_string(),
+            }),
+            log: Mutex::new(Vec::new()),
+        });
+        app.attach(from_fn(
+            |req: Req, state: Arc<AppState>, next: Next<AppState>| async move {
+                state.log.lock().unwrap().push(req.uri().path().to_string());
+                next.run(req).await
+            },
+        ));
+
+        let mut router = Router::new();
+        router.get(
+            "/{name}",
+            |State(config): State<Arc<Config>>, Path(name): Path<String>| async move {
+                format!("{} {}", config.greeting, name)
+            },
+        );
+        app.nest("/greet", router);
+        app.get("/log", |state: StateRef<AppState>| async move {
+            state.log.lock().unwrap().join(",")
+        });
+        let client = app.into_test_client();
+
+        let res = client.get("/greet/ada").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "hello ada");
+        let res = client.get("/log").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "/greet/ada,/log");
+    }
+
+    #[tokio::test]
+    async fn test_optional_extractors() {
+        use crate::{Foton, Res};
+
+        #[derive(serde::Deserialize, serde::Serialize)]
+        struct Page {
+            page: Option<u32>,
+            #[serde(default)]
+            sort: String,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Search {
+            q: String,
+        }
+
+        let mut app = Foton::new();
+        app.set_body_limit(64);
+        app.get("/items", |Query(q): Query<Page>| async move {
+            format!("{:?} {:?}", q.page, q.sort)
+        });
+        app.get("/search", |q: Option<Query<Search>>| async move {
+            q.map_or("no query".to_string(), |Query(s)| s.q)
+        });
+        app.post("/items", |body: Option<Json<Page>>| async move {
+            match body {
+                Some(Json(p)) => format!("page {:?}", p.page),
+                None => "no body".to_string(),
+            }
+        });
+        app.get(
+            "/items/{id}",
+            |id: std::result::Result<Path<u64>, Error>| async move {
+                match id {
+                    Ok(Path(id)) => Res::text(id.to_string()),
+                    Err(e) => Res::builder()
+                        .status(422)
+                        .text(format!("custom: {}", e.status_code())),
+                }
+            },
+        );
+        let client = app.into_test_client();
+
+        let res = client.get("/items").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "None \"\"");
+        let res = client.get("/items?page=2&sort=name").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "Some(2) \"name\"");
+
+        let res = client.post("/items").text("oops").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "no body");
+        let res = client
+            .post("/items")
+            .json(&Page {
+                page: Some(3),
+                sort: String::new(),
+            })
+            .send()
+            .await;
+        assert_eq!(res.into_text().await.unwrap(), "page Some(3)");
+
+        // Over the body limit is not "no body"
+        let res = client
+            .post("/items")
+            .header("content-type", "application/json")
+            .text(format!("{{\"sort\": \"{}\"}}", "x".repeat(100)))
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 413);
+
+        // A missing query string leaves required fields unset
+        let res = client.get("/search").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "no query");
+        let res = client.get("/search?q=rust").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "rust");
+
+        let res = client.get("/items/9").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "9");
+        let res = client.get("/items/x").send().await;
+        assert_eq!(res.status_code(), 422);
+        assert_eq!(res.into_text().await.unwrap(), "custom: 400");
+    }
+
+    const BODY: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Hello\r\nworld\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        --XyZ is not a delimiter here\r\n\
+        --XyZ--\r\n";
+
+    fn multipart_req(body: impl AsRef<[u8]>, chunk_size: usize) -> Req {
+        use http_body_util::StreamBody;
+        use hyper::body::Frame;
+
+        let frames: Vec<_> = body
+            .as_ref()
+            .chunks(chunk_size)
+            .map(|c| Ok::<_, std::convert::Infallible>(Frame::data(Bytes::copy_from_slice(c))))
+            .collect();
+        let req = hyper::Request::builder()
+            .header("content-type", "multipart/form-data; boundary=\"XyZ\"")
+            .body(StreamBody::new(futures_util::stream::iter(frames)))
+            .unwrap();
+        Req::from_hyper(req)
+    }
+
+    #[tokio::test]
+    async fn test_multipart_split_into_single_bytes() {
+        let mut req = multipart_req(BODY, 1);
+        let mut form = Multipart::from_request(&mut req, &Arc::new(()))
+            .await
+            .unwrap();
+
+        let field = form.next_field().await.unwrap().unwrap();
+        assert_eq!(field.name(), Some("title"));
+        assert_eq!(field.file_name(), None);
+        assert_eq!(field.text().await.unwrap(), "Hello\r\nworld");
+
+        let field = form.next_field().await.unwrap().unwrap();
+        assert_eq!(field.name(), Some("upload"));
+        assert_eq!(field.file_name(), Some("a \"b\".txt"));
+        assert_eq!(field.content_type(), Some("text/plain"));
+        assert_eq!(field.text().await.unwrap(), "--XyZ is not a delimiter here");
+
+        assert!(form.next_field().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_multipart_skips_unread_fields_and_saves_file() {
+        let mut req = multipart_req(BODY, 7);
+        let mut form = Multipart::from_request(&mut req, &Arc::new(()))
+            .await
+            .unwrap();
+
+        form.next_field().await.unwrap().unwrap();
+        let field = form.next_field().await.unwrap().unwrap();
+
+        let path = TempPath::new("multipart");
+        assert_eq!(field.save_to(&path).await.unwrap(), 29);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "--XyZ is not a delimiter here"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multipart_limits_and_errors() {
+        let mut req = multipart_req(BODY, 16);
+        req.extensions_mut()
+            .insert(MultipartLimits::new().field_limit(4));
+        let mut form = Multipart::from_request(&mut req, &Arc::new(()))
+            .await
+            .unwrap();
+        let err = form.next_field().await.unwrap().unwrap().bytes().await;
+        assert_eq!(err.unwrap_err().status_code(), 413);
+
+        let mut req = multipart_req("--XyZ\r\n\r\nunterminated", 4);
+        let mut form = Multipart::from_request(&mut req, &Arc::new(()))
+            .await
+            .unwrap();
+        let err = form.next_field().await.unwrap().unwrap().bytes().await;
+        assert_eq!(err.unwrap_err().status_code(), 400);
+
+        // Oversized part headers are rejected even when they arrive in one chunk
+        let padding = "a".repeat(MULTIPART_HEADER_LIMIT);
+        let body = format!(
+            "--XyZ\r\nX-Padding: {}\r\n\r\nvalue\r\n--XyZ--\r\n",
+            padding
+        );
+        let mut req = multipart_req(&body, body.len());
+        let mut form = Multipart::from_request(&mut req, &Arc::new(()))
+            .await
+            .unwrap();
+        let Err(err) = form.next_field().await else {
+            panic!("oversized part headers were accepted");
+        };
+        assert_eq!(err.status_code(), 400);
+
+        let mut app = crate::Foton::new();
+        app.post("/", |_: Multipart| async { "ok" });
+        let res = app
+            .into_test_client()
+            .post("/")
+            .header("content-type", "multipart/form-data")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 400);
+    }
 }
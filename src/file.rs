@@ -0,0 +1,496 @@
+//! Conditional and range requests for file responses.
+//!
+//! [`Res::file`] attaches [`FileMeta`] to its response. The handler pipeline
+//! captures the request's validators and `Range` header in [`Preconditions`]
+//! and turns matching file responses into 304, 206 or 416 responses.
+
+use bytes::Bytes;
+use futures_util::{StreamExt, TryStreamExt};
+use http_body_util::{BodyExt, Empty, StreamBody};
+use hyper::body::Frame;
+use hyper::{Method, Response, StatusCode, header};
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::res::BoxBody;
+use crate::{Error, Req, Res, StreamSender};
+
+/// Maximum number of ranges honored in one request; more are served in full.
+const MAX_RANGES: usize = 32;
+
+/// Validators of a file served by [`Res::file`].
+#[derive(Debug, Clone)]
+pub(crate) struct FileMeta {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+    etag: String,
+}
+
+impl FileMeta {
+    pub(crate) fn new(path: PathBuf, metadata: &std::fs::Metadata) -> Self {
+        let modified = metadata.modified().ok();
+        let stamp = modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+
+        Self {
+            path,
+            len: metadata.len(),
+            modified,
+            etag: format!("\"{:x}-{:x}\"", stamp, metadata.len()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn etag(&self) -> &str {
+        &self.etag
+    }
+
+    pub(crate) fn last_modified(&self) -> Option<String> {
+        self.modified.map(httpdate::fmt_http_date)
+    }
+
+    fn modified_secs(&self) -> Option<u64> {
+        self.modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+    }
+
+    fn same_file(&self, metadata: &std::fs::Metadata) -> bool {
+        metadata.len() == self.len && metadata.modified().ok() == self.modified
+    }
+}
+
+/// Conditional and range headers captured before the request is handled.
+pub(crate) struct Preconditions {
+    method: Method,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+    range: Option<String>,
+    if_range: Option<String>,
+}
+
+impl Preconditions {
+    /// Capture the relevant headers, or `None` if there are none.
+    pub(crate) fn from_req(req: &Req) -> Option<Self> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return None;
+        }
+
+        let header = |name: header::HeaderName| req.header(name.as_str()).map(str::to_string);
+        let preconditions = Self {
+            method: req.method().clone(),
+            if_none_match: header(header::IF_NONE_MATCH),
+            if_modified_since: header(header::IF_MODIFIED_SINCE),
+            range: header(header::RANGE),
+            if_range: header(header::IF_RANGE),
+        };
+
+        let any = preconditions.if_none_match.is_some()
+            || preconditions.if_modified_since.is_some()
+            || preconditions.range.is_some();
+        any.then_some(preconditions)
+    }
+
+    /// Answer a file response with 304, 206 or 416 where the headers ask for it.
+    pub(crate) async fn apply(self, res: Res) -> Res {
+        if res.status_code() != StatusCode::OK || res.error().is_some() {
+            return res;
+        }
+        let response = res.into_hyper();
+        let Some(meta) = response.extensions().get::<FileMeta>().cloned() else {
+            return Res::from_hyper(response);
+        };
+
+        if self.not_modified(&meta) {
+            let (mut parts, _) = response.into_parts();
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(header::CONTENT_LENGTH);
+            parts.headers.remove(header::CONTENT_TYPE);
+            return Res::from_hyper(Response::from_parts(parts, empty()));
+        }
+
+        let Some(range) = self.range.as_deref() else {
+            return Res::from_hyper(response);
+        };
+        if self.method != Method::GET || !self.if_range_matches(&meta) {
+            return Res::from_hyper(response);
+        }
+
+        match parse_range(range, meta.len) {
+            RangeSet::Full => Res::from_hyper(response),
+            RangeSet::Unsatisfiable => {
+                let (mut parts, _) = response.into_parts();
+                parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+                parts.headers.remove(header::CONTENT_TYPE);
+                parts.headers.insert(header::CONTENT_LENGTH, 0.into());
+                insert(
+                    &mut parts.headers,
+                    header::CONTENT_RANGE,
+                    format!("bytes */{}", meta.len),
+                );
+                Res::from_hyper(Response::from_parts(parts, empty()))
+            }
+            RangeSet::Ranges(ranges) => partial(response, &meta, ranges).await,
+        }
+    }
+
+    fn not_modified(&self, meta: &FileMeta) -> bool {
+        // If-None-Match takes precedence over If-Modified-Since
+        if let Some(if_none_match) = &self.if_none_match {
+            return if_none_match.trim() == "*"
+                || if_none_match
+                    .split(',')
+                    .any(|tag| weak_tag(tag.trim()) == weak_tag(&meta.etag));
+        }
+
+        match (&self.if_modified_since, meta.modified_secs()) {
+            (Some(since), Some(modified)) => httpdate::parse_http_date(since)
+                .ok()
+                .and_then(|since| since.duration_since(UNIX_EPOCH).ok())
+                .is_some_and(|since| modified <= since.as_secs()),
+            _ => false,
+        }
+    }
+
+    fn if_range_matches(&self, meta: &FileMeta) -> bool {
+        let Some(if_range) = self.if_range.as_deref().map(str::trim) else {
+            return true;
+        };
+
+        // Entity tags need a strong match, dates an exact one
+        if if_range.starts_with('"') || if_range.starts_with("W/") {
+            if_range == meta.etag
+        } else {
+            meta.last_modified().as_deref() == Some(if_range)
+        }
+    }
+}
+
+fn weak_tag(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn empty() -> BoxBody {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn insert(headers: &mut header::HeaderMap, name: header::HeaderName, value: String) {
+    if let Ok(value) = header::HeaderValue::from_str(&value) {
+        headers.insert(name, value);
+    }
+}
+
+/// Outcome of parsing a `Range` header against a file length.
+#[derive(Debug, PartialEq)]
+enum RangeSet {
+    /// Invalid or unsupported header; serve the whole file.
+    Full,
+    /// No range overlaps the file.
+    Unsatisfiable,
+    /// Sorted, merged, inclusive byte ranges.
+    Ranges(Vec<(u64, u64)>),
+}
+
+fn parse_range(value: &str, len: u64) -> RangeSet {
+    let value = value.trim();
+    let Some(spec) = value
+        .get(..6)
+        .filter(|unit| unit.eq_ignore_ascii_case("bytes="))
+        .map(|_| &value[6..])
+    else {
+        return RangeSet::Full;
+    };
+
+    let mut ranges = Vec::new();
+    let specs: Vec<&str> = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return RangeSet::Full;
+    }
+
+    for spec in specs {
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeSet::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+
+        if start.is_empty() {
+            // Suffix range: the last `n` bytes
+            let Ok(n) = end.parse::<u64>() else {
+                return RangeSet::Full;
+            };
+            if n > 0 && len > 0 {
+                ranges.push((len.saturating_sub(n), len - 1));
+            }
+            continue;
+        }
+
+        let Ok(start) = start.parse::<u64>() else {
+            return RangeSet::Full;
+        };
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            match end.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return RangeSet::Full,
+            }
+        };
+        if start < len {
+            ranges.push((start, end.min(len - 1)));
+        }
+    }
+
+    if ranges.is_empty() {
+        return RangeSet::Unsatisfiable;
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    RangeSet::Ranges(merged)
+}
+
+/// Build a 206 response for the given ranges.
+///
+/// Falls back to the original response if the file changed since it was opened.
+async fn partial(response: Response<BoxBody>, meta: &FileMeta, ranges: Vec<(u64, u64)>) -> Res {
+    let mut file = match File::open(&meta.path).await {
+        Ok(file) => file,
+        Err(_) => return Res::from_hyper(response),
+    };
+    match file.metadata().await {
+        Ok(metadata) if meta.same_file(&metadata) => {}
+        _ => return Res::from_hyper(response),
+    }
+
+    if let [(start, end)] = ranges[..] {
+        if file.seek(SeekFrom::Start(start)).await.is_err() {
+            return Res::from_hyper(response);
+        }
+        let body = ReaderStream::new(file.take(end - start + 1))
+            .map_ok(Frame::data)
+            .map_err(Error::from);
+
+        let (mut parts, _) = response.into_parts();
+        parts.status = StatusCode::PARTIAL_CONTENT;
+        parts
+            .headers
+            .insert(header::CONTENT_LENGTH, (end - start + 1).into());
+        insert(
+            &mut parts.headers,
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, meta.len),
+        );
+        return Res::from_hyper(Response::from_parts(
+            parts,
+            BodyExt::boxed(StreamBody::new(body)),
+        ));
+    }
+
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::PARTIAL_CONTENT;
+    let boundary = uuid::Uuid::new_v4().simple().to_string();
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    let sections: Vec<(Bytes, u64, u64)> = ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let head = format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                boundary, content_type, start, end, meta.len
+            );
+            (Bytes::from(head), start, end)
+        })
+        .collect();
+    let closing = Bytes::from(format!("\r\n--{}--\r\n", boundary));
+    let length = sections
+        .iter()
+        .map(|(head, start, end)| head.len() as u64 + end - start + 1)
+        .sum::<u64>()
+        + closing.len() as u64;
+
+    let body = Res::stream(move |mut tx: StreamSender| async move {
+        for (head, start, end) in sections {
+            if tx.send(head).await.is_err() || file.seek(SeekFrom::Start(start)).await.is_err() {
+                return;
+            }
+            let mut part = ReaderStream::new((&mut file).take(end - start + 1));
+            while let Some(Ok(chunk)) = part.next().await {
+                if tx.send(chunk).await.is_err() {
+                    return;
+                }
+            }
+        }
+        tx.send(closing).await.ok();
+    })
+    .into_hyper()
+    .into_body();
+
+    parts.headers.insert(header::CONTENT_LENGTH, length.into());
+    insert(
+        &mut parts.headers,
+        header::CONTENT_TYPE,
+        format!("multipart/byteranges; boundary={}", boundary),
+    );
+    Res::from_hyper(Response::from_parts(parts, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Foton;
+    use crate::test::TempPath;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-4", 10), RangeSet::Ranges(vec![(0, 4)]));
+        assert_eq!(parse_range("bytes=-3", 10), RangeSet::Ranges(vec![(7, 9)]));
+        assert_eq!(parse_range("bytes=8-", 10), RangeSet::Ranges(vec![(8, 9)]));
+        assert_eq!(
+            parse_range("bytes=5-100", 10),
+            RangeSet::Ranges(vec![(5, 9)])
+        );
+        assert_eq!(
+            parse_range("bytes=6-7, 0-1, 1-3", 10),
+            RangeSet::Ranges(vec![(0, 3), (6, 7)])
+        );
+        assert_eq!(parse_range("bytes=10-", 10), RangeSet::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), RangeSet::Unsatisfiable);
+        assert_eq!(parse_range("bytes=4-2", 10), RangeSet::Full);
+        assert_eq!(parse_range("items=0-1", 10), RangeSet::Full);
+        assert_eq!(parse_range("bytes=a-b", 10), RangeSet::Full);
+    }
+
+    async fn client() -> (crate::test::TestClient, TempPath) {
+        let path = TempPath::new("range.txt");
+        tokio::fs::write(&path, "0123456789").await.unwrap();
+
+        let mut app = Foton::new();
+        let file = path.to_path_buf();
+        app.get("/file", move |_: Req| {
+            let file = file.clone();
+            async move { Res::file(file).await }
+        });
+        (app.into_test_client(), path)
+    }
+
+    #[tokio::test]
+    async fn test_file_headers_and_conditional_get() {
+        let (client, _path) = client().await;
+
+        let res = client.get("/file").send().await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.headers()["content-length"], "10");
+        assert_eq!(res.headers()["accept-ranges"], "bytes");
+        let etag = res.headers()["etag"].to_str().unwrap().to_string();
+        let modified = res.headers()["last-modified"].to_str().unwrap().to_string();
+
+        let res = client
+            .get("/file")
+            .header("if-none-match", format!("W/{}", etag))
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 304);
+        assert!(res.into_bytes().await.unwrap().is_empty());
+
+        let res = client
+            .get("/file")
+            .header("if-modified-since", &modified)
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 304);
+
+        let res = client
+            .get("/file")
+            .header("if-none-match", "\"other\"")
+            .header("if-modified-since", &modified)
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_range_requests() {
+        let (client, _path) = client().await;
+
+        let res = client
+            .get("/file")
+            .header("range", "bytes=2-4")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 206);
+        assert_eq!(res.headers()["content-range"], "bytes 2-4/10");
+        assert_eq!(res.headers()["content-length"], "3");
+        assert_eq!(res.into_text().await.unwrap(), "234");
+
+        let res = client
+            .get("/file")
+            .header("range", "bytes=20-")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 416);
+        assert_eq!(res.headers()["content-range"], "bytes */10");
+
+        let res = client
+            .get("/file")
+            .header("range", "bytes=0-1")
+            .header("if-range", "\"stale\"")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.into_text().await.unwrap(), "0123456789");
+
+        let res = client
+            .get("/file")
+            .header("range", "bytes=0-1,-2")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 206);
+        let content_type = res.headers()["content-type"].to_str().unwrap().to_string();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_string();
+        let length: usize = res.headers()["content-length"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = res.into_text().await.unwrap();
+        assert_eq!(body.len(), length);
+        assert_eq!(
+            body,
+            format!(
+                "\r\n--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+                 \r\n--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+                 \r\n--{b}--\r\n",
+                b = boundary
+            )
+        );
+    }
+}
@@ -3,7 +3,7 @@
 use async_trait::async_trait;
 use std::sync::Arc;
 
-use crate::extractors::FromRequest;
+use crate::extractors::{FromRequest, RejectionHeaders};
 use crate::{IntoRes, Req, Res};
 
 /// Convert function to handler.
@@ -18,12 +18,33 @@ pub trait Handler<S = ()>: Send + Sync + 'static {
     async fn call(&self, req: Req, state: Arc<S>) -> Res;
 }
 
+/// Marker for types implementing [`Handler`] directly.
+pub struct HandlerType;
+
+impl<H, S> IntoHandler<S, HandlerType> for H
+where
+    H: Handler<S>,
+{
+    #[inline]
+    fn into_handler(self) -> Arc<dyn Handler<S>> {
+        Arc::new(self)
+    }
+}
+
 /// Extract or return error response.
 macro_rules! extract_or_return {
     ($req:expr, $state:expr, $extractor:ty) => {
         match <$extractor as FromRequest<_>>::from_request($req, $state).await {
             Ok(v) => v,
-            Err(e) => return e.into_res(),
+            Err(e) => {
+                let mut res = e.into_res();
+                if let Some(RejectionHeaders(headers)) = $req.extensions_mut().remove() {
+                    for (name, value) in &headers {
+                        res.headers_mut().append(name, value.clone());
+                    }
+                }
+                return res;
+            }
         }
     };
 }
@@ -98,7 +119,7 @@ macro_rules! impl_handler {
                 F: Fn($($extractor),+) -> Fut + Send + Sync + 'static,
                 Fut: std::future::Future + Send + 'static,
                 Fut::Output: IntoRes,
-                S: Clone + Send + Sync + 'static,
+                S: Send + Sync + 'static,
                 $($extractor: FromRequest<S> + Send + Sync + 'static,)+
             {
                 #[inline]
@@ -118,7 +139,7 @@ macro_rules! impl_handler {
                 F: Fn($($extractor),+) -> Fut + Send + Sync + 'static,
                 Fut: std::future::Future + Send + 'static,
                 Fut::Output: IntoRes,
-                S: Clone + Send + Sync + 'static,
+                S: Send + Sync + 'static,
                 $($extractor: FromRequest<S> + Send + Sync + 'static,)+
             {
                 #[inline]
@@ -49,11 +49,11 @@ impl<T: IntoRes> IntoRes for Result<T, Error> {
 
 impl IntoRes for Error {
     fn into_res(self) -> Res {
-        match self {
+        let res = match &self {
             Error::Status(code, Some(msg)) => Res::builder()
-                .status(code)
+                .status(*code)
                 .text(format!("{} {}", code, msg)),
-            Error::Status(code, None) => Res::status(code),
+            Error::Status(code, None) => Res::status(*code),
             Error::Json(e) => Res::builder()
                 .status(400)
                 .text(format!("JSON error: {}", e)),
@@ -61,8 +61,10 @@ impl IntoRes for Error {
                 .status(500)
                 .text(format!("HTTP error: {}", e)),
             Error::Io(e) => Res::builder().status(500).text(format!("IO error: {}", e)),
-            Error::Custom(msg) => Res::builder().status(500).text(msg),
-        }
+            Error::Custom(msg) => Res::builder().status(500).text(msg.clone()),
+            Error::Route(e) => Res::builder().status(500).text(e.to_string()),
+        };
+        res.with_error(self)
     }
 }
 
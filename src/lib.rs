@@ -1,12 +1,12 @@
 //! Foton - Fast, minimal, Rust-native web framework.
 //!
 //! ```rust,no_run
-//! use foton::{Foton, Res};
+//! use foton::{Foton, Req, Res};
 //!
 //! #[tokio::main]
 //! async fn main() {
 //!     let mut app = Foton::new();
-//!     app.get("/", |_| async { Res::text("Hello") });
+//!     app.get("/", |_: Req| async { Res::text("Hello") });
 //!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
 //! }
 //! ```
@@ -15,44 +15,97 @@
 #![warn(rust_2018_idioms)]
 
 mod api;
+pub mod auth;
 mod config;
+pub mod connect_info;
+pub mod cookies;
+pub mod cors;
+#[cfg(feature = "compression")]
+mod decompression;
 mod error;
 pub mod error_handler;
 pub mod extensions;
 pub mod extractors;
+mod file;
 mod handler;
 mod into_res;
 mod middleware;
+mod mime;
+mod path_de;
+mod proxy_protocol;
+pub mod rate_limit;
 mod req;
+pub mod request_id;
 mod res;
 pub mod route;
 mod router;
+pub mod serve_dir;
+pub mod session;
+pub mod sse;
+pub mod test;
+
+#[cfg(feature = "compression")]
+pub mod compression;
+
+#[cfg(feature = "jwt")]
+pub mod jwt;
+
+#[cfg(feature = "tls")]
+pub mod tls;
 
 #[cfg(feature = "websocket")]
 pub mod websocket;
 
 pub use api::{Foton, app, app_with_state};
+pub use auth::{BasicAuth, BearerToken};
 pub use config::ServerConfig;
-pub use error::{Error, Result};
+pub use connect_info::{ClientIp, ConnectInfo};
+pub use cookies::Cookies;
+pub use cors::Cors;
+pub use error::{Error, Result, RouteError, RouteIssue};
 pub use error_handler::ErrorHandler;
 pub use extensions::Extensions;
-pub use extractors::{BodyBytes, Form, FromRequest, Headers, Json, Path, Query, State};
+pub use extractors::{
+    BodyBytes, Form, FromRef, FromRequest, Headers, Json, Multipart, MultipartLimits, Path, Query,
+    State, StateRef,
+};
 pub use handler::{FnHandler, FnHandler1, FnHandler2, FnHandler3, Handler};
+pub use hyper::Method;
 pub use into_res::IntoRes;
 pub use middleware::{Middleware, Next, from_fn, middleware};
-pub use req::Req;
+pub use rate_limit::RateLimit;
+pub use req::{BodyStream, Req};
+pub use request_id::RequestId;
 pub use res::{Res, ResBuilder, StreamSender};
 pub use route::Route;
 pub use router::Router;
+pub use serve_dir::ServeDir;
+pub use session::{Session, Sessions};
+pub use sse::{Event, LastEventId, Sse, SseSender};
+
+#[cfg(feature = "compression")]
+pub use compression::Compression;
+
+#[cfg(feature = "jwt")]
+pub use jwt::{Jwt, JwtConfig};
+
+#[cfg(feature = "secure-cookies")]
+pub use cookies::{PrivateCookies, SignedCookies};
+
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
 
 #[cfg(feature = "websocket")]
 pub use websocket::{CloseFrame, Message, WebSocket, WebSocketHandler, WebSocketUpgrade};
 
 /// Common types and traits.
 pub mod prelude {
-    pub use crate::extractors::{BodyBytes, Form, FromRequest, Headers, Json, Path, Query, State};
+    pub use crate::extractors::{
+        BodyBytes, Form, FromRef, FromRequest, Headers, Json, Multipart, Path, Query, State,
+        StateRef,
+    };
     pub use crate::{
-        Error, ErrorHandler, Extensions, Foton, Handler, IntoRes, Middleware, Next, Req, Res,
-        Result, Route, Router, app, app_with_state, from_fn, middleware,
+        BodyStream, Cookies, Error, ErrorHandler, Extensions, Foton, Handler, IntoRes, Middleware,
+        Next, Req, Res, Result, Route, Router, app, app_with_state, from_fn, middleware,
     };
 }
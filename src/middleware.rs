@@ -15,12 +15,14 @@ pub trait Middleware<S = ()>: Send + Sync + 'static {
 
 /// Next middleware/handler in chain.
 pub struct Next<S = ()> {
-    pub(crate) handler: Arc<dyn Fn(Req, Arc<S>) -> BoxFuture<Res> + Send + Sync>,
+    pub(crate) handler: NextFn<S>,
     pub(crate) state: Arc<S>,
 }
 
 type BoxFuture<T> = std::pin::Pin<Box<dyn Future<Output = T> + Send>>;
 
+pub(crate) type NextFn<S> = Arc<dyn Fn(Req, Arc<S>) -> BoxFuture<Res> + Send + Sync>;
+
 impl<S: 'static> Next<S> {
     /// Create next handler.
     #[inline]
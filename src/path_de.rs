@@ -0,0 +1,490 @@
+//! Deserializer for typed path parameters.
+//!
+//! Values are percent-decoded and parsed on demand, so `Path<u64>`,
+//! `Path<(Uuid, i32)>` and structs with numeric, bool or enum fields work.
+//! Errors name the parameter that failed.
+
+use percent_encoding::percent_decode_str;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use std::borrow::Cow;
+use std::fmt;
+
+/// Deserialize route parameters, in route order, into `T`.
+pub(crate) fn from_params<T: DeserializeOwned>(
+    params: &[(String, String)],
+) -> Result<T, PathError> {
+    let decoded = params
+        .iter()
+        .map(|(key, value)| {
+            percent_decode_str(value)
+                .decode_utf8()
+                .map(|value| (key.as_str(), value))
+                .map_err(|_| PathError::param(key, "not valid UTF-8 after percent-decoding"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    T::deserialize(PathDeserializer { params: &decoded })
+}
+
+/// Path parameter error, naming the parameter when known.
+#[derive(Debug)]
+pub(crate) struct PathError {
+    param: Option<String>,
+    message: String,
+}
+
+impl PathError {
+    fn param(key: &str, message: impl fmt::Display) -> Self {
+        Self {
+            param: Some(key.to_string()),
+            message: message.to_string(),
+        }
+    }
+
+    /// Attach `key` to errors raised by visitors, such as `Uuid`'s.
+    fn in_param(mut self, key: &str) -> Self {
+        self.param.get_or_insert_with(|| key.to_string());
+        self
+    }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.param {
+            Some(param) => write!(f, "Invalid path parameter `{}`: {}", param, self.message),
+            None => write!(f, "Invalid path parameters: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+impl de::Error for PathError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self {
+            param: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+type Params<'a> = [(&'a str, Cow<'a, str>)];
+
+/// Top-level deserializer over all parameters.
+struct PathDeserializer<'a> {
+    params: &'a Params<'a>,
+}
+
+impl<'a> PathDeserializer<'a> {
+    /// The only parameter, for targets like `Path<u64>`.
+    fn single(&self) -> Result<ValueDeserializer<'a>, PathError> {
+        match self.params {
+            [(key, value)] => Ok(ValueDeserializer { key, value }),
+            _ => Err(de::Error::custom(format!(
+                "expected 1 parameter, found {}",
+                self.params.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! forward_to_single {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+                let value = self.single()?;
+                let key = value.key;
+                value.$method(visitor).map_err(|e| e.in_param(key))
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for PathDeserializer<'_> {
+    type Error = PathError;
+
+    forward_to_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf deserialize_identifier
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_seq(SeqAccess {
+            params: self.params.iter(),
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        if self.params.len() != len {
+            return Err(de::Error::custom(format!(
+                "expected {} parameters, found {}",
+                len,
+                self.params.len()
+            )));
+        }
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_map(MapAccess {
+            params: self.params.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        let value = self.single()?;
+        let key = value.key;
+        value
+            .deserialize_enum(name, variants, visitor)
+            .map_err(|e| e.in_param(key))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_unit()
+    }
+}
+
+struct SeqAccess<'a, I: Iterator<Item = &'a (&'a str, Cow<'a, str>)>> {
+    params: I,
+}
+
+impl<'a, 'de, I> de::SeqAccess<'de> for SeqAccess<'a, I>
+where
+    I: Iterator<Item = &'a (&'a str, Cow<'a, str>)>,
+{
+    type Error = PathError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, PathError> {
+        match self.params.next() {
+            Some((key, value)) => seed
+                .deserialize(ValueDeserializer { key, value })
+                .map(Some)
+                .map_err(|e| e.in_param(key)),
+            None => Ok(None),
+        }
+    }
+}
+
+struct MapAccess<'a, I: Iterator<Item = &'a (&'a str, Cow<'a, str>)>> {
+    params: I,
+    value: Option<&'a (&'a str, Cow<'a, str>)>,
+}
+
+impl<'a, 'de, I> de::MapAccess<'de> for MapAccess<'a, I>
+where
+    I: Iterator<Item = &'a (&'a str, Cow<'a, str>)>,
+{
+    type Error = PathError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, PathError> {
+        match self.params.next() {
+            Some(param) => {
+                self.value = Some(param);
+                seed.deserialize(param.0.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, PathError> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| <PathError as de::Error>::custom("value requested before key"))?;
+        seed.deserialize(ValueDeserializer { key, value })
+            .map_err(|e| e.in_param(key))
+    }
+}
+
+/// Deserializer for a single parameter value.
+struct ValueDeserializer<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident $ty:ty,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+                let value = self.value.parse::<$ty>().map_err(|_| {
+                    PathError::param(
+                        self.key,
+                        format!("cannot parse {:?} as {}", self.value, stringify!($ty)),
+                    )
+                })?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = PathError;
+
+    parse_value! {
+        deserialize_bool => visit_bool bool,
+        deserialize_i8 => visit_i8 i8,
+        deserialize_i16 => visit_i16 i16,
+        deserialize_i32 => visit_i32 i32,
+        deserialize_i64 => visit_i64 i64,
+        deserialize_i128 => visit_i128 i128,
+        deserialize_u8 => visit_u8 u8,
+        deserialize_u16 => visit_u16 u16,
+        deserialize_u32 => visit_u32 u32,
+        deserialize_u64 => visit_u64 u64,
+        deserialize_u128 => visit_u128 u128,
+        deserialize_f32 => visit_f32 f32,
+        deserialize_f64 => visit_f64 f64,
+        deserialize_char => visit_char char,
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_str(self.value)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_str(self.value)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_string(self.value.to_string())
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_str(self.value)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_bytes(self.value.as_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_byte_buf(self.value.as_bytes().to_vec())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, PathError> {
+        if !variants.contains(&self.value) {
+            return Err(PathError::param(
+                self.key,
+                format!(
+                    "unknown variant {:?}, expected one of {}",
+                    self.value,
+                    variants.join(", ")
+                ),
+            ));
+        }
+        visitor.visit_enum(self.value.into_deserializer())
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, PathError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, PathError> {
+        Err(self.unsupported("sequence"))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, PathError> {
+        Err(self.unsupported("tuple"))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, PathError> {
+        Err(self.unsupported("tuple struct"))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, PathError> {
+        Err(self.unsupported("map"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, PathError> {
+        Err(self.unsupported("struct"))
+    }
+}
+
+impl ValueDeserializer<'_> {
+    fn unsupported(&self, kind: &str) -> PathError {
+        PathError::param(self.key, format!("a single segment cannot hold a {}", kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_scalars_and_tuples() {
+        let id: u64 = from_params(&params(&[("id", "42")])).unwrap();
+        assert_eq!(id, 42);
+
+        let (name, page, active): (String, i32, bool) = from_params(&params(&[
+            ("name", "caf%C3%A9%20au%20lait"),
+            ("page", "-3"),
+            ("active", "true"),
+        ]))
+        .unwrap();
+        assert_eq!((name.as_str(), page, active), ("café au lait", -3, true));
+
+        let err = from_params::<(u32, u32)>(&params(&[("a", "1"), ("b", "x")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid path parameter `b`: cannot parse \"x\" as u32"
+        );
+
+        let err = from_params::<u64>(&params(&[("a", "1"), ("b", "2")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid path parameters: expected 1 parameter, found 2"
+        );
+    }
+
+    #[test]
+    fn test_structs_and_enums() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Kind {
+            Post,
+            Page,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Params {
+            kind: Kind,
+            id: u32,
+            draft: Option<bool>,
+        }
+
+        let value: Params = from_params(&params(&[("id", "7"), ("kind", "page")])).unwrap();
+        assert_eq!(
+            value,
+            Params {
+                kind: Kind::Page,
+                id: 7,
+                draft: None
+            }
+        );
+
+        let kind: Kind = from_params(&params(&[("kind", "post")])).unwrap();
+        assert_eq!(kind, Kind::Post);
+
+        let err = from_params::<Params>(&params(&[("id", "7"), ("kind", "video")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid path parameter `kind`: unknown variant \"video\", expected one of post, page"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! PROXY protocol v1 and v2 headers.
+//!
+//! See <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest v1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// Addresses of the original connection, as reported by the proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProxiedAddrs {
+    /// The client.
+    pub(crate) source: SocketAddr,
+    /// The address the client connected to.
+    pub(crate) destination: SocketAddr,
+}
+
+/// Read a PROXY header without consuming any bytes after it.
+///
+/// Returns the addresses the proxy reported, or `None` for `UNKNOWN` (v1),
+/// `LOCAL` (v2) and non-IP address families, where the connection's own
+/// addresses apply.
+pub(crate) async fn read_header<R>(io: &mut R) -> io::Result<Option<ProxiedAddrs>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut start = [0u8; 5];
+    io.read_exact(&mut start).await?;
+
+    if &start == b"PROXY" {
+        read_v1(io).await
+    } else if start == V2_SIGNATURE[..5] {
+        read_v2(io).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+async fn read_v1<R>(io: &mut R) -> io::Result<Option<ProxiedAddrs>>
+where
+    R: AsyncRead + Unpin,
+{
+    // Read byte by byte so nothing past the CRLF is consumed
+    let mut line = b"PROXY".to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(invalid("PROXY v1 header too long"));
+        }
+        line.push(io.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), src, dst, sport, dport] => {
+            let is_v4 = *family == "TCP4";
+            Ok(Some(ProxiedAddrs {
+                source: v1_addr(src, sport, is_v4)?,
+                destination: v1_addr(dst, dport, is_v4)?,
+            }))
+        }
+        _ => Err(invalid("malformed PROXY v1 header")),
+    }
+}
+
+fn v1_addr(ip: &str, port: &str, is_v4: bool) -> io::Result<SocketAddr> {
+    let ip: IpAddr = ip
+        .parse()
+        .map_err(|_| invalid("invalid PROXY v1 address"))?;
+    if ip.is_ipv4() != is_v4 {
+        return Err(invalid("PROXY v1 address does not match its family"));
+    }
+    let port: u16 = port.parse().map_err(|_| invalid("invalid PROXY v1 port"))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+async fn read_v2<R>(io: &mut R) -> io::Result<Option<ProxiedAddrs>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut rest = [0u8; 11];
+    io.read_exact(&mut rest).await?;
+    if rest[..7] != V2_SIGNATURE[5..] {
+        return Err(invalid("missing PROXY protocol header"));
+    }
+
+    let version_command = rest[7];
+    let family = rest[8];
+    let len = u16::from_be_bytes([rest[9], rest[10]]) as usize;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    let mut payload = vec![0u8; len];
+    io.read_exact(&mut payload).await?;
+
+    match version_command & 0x0f {
+        // LOCAL: health checks from the proxy itself
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid("unsupported PROXY v2 command")),
+    }
+
+    let too_short = || invalid("PROXY v2 address block too short");
+    match family >> 4 {
+        0x1 => {
+            let block: &[u8; 12] = payload
+                .get(..12)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(too_short)?;
+            let addr = |ip: &[u8], port: &[u8]| {
+                let ip = Ipv4Addr::from([ip[0], ip[1], ip[2], ip[3]]);
+                SocketAddr::new(ip.into(), u16::from_be_bytes([port[0], port[1]]))
+            };
+            Ok(Some(ProxiedAddrs {
+                source: addr(&block[..4], &block[8..10]),
+                destination: addr(&block[4..8], &block[10..12]),
+            }))
+        }
+        0x2 => {
+            let block: &[u8; 36] = payload
+                .get(..36)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(too_short)?;
+            let addr = |ip: &[u8], port: &[u8]| {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(ip);
+                let ip = Ipv6Addr::from(octets);
+                SocketAddr::new(ip.into(), u16::from_be_bytes([port[0], port[1]]))
+            };
+            Ok(Some(ProxiedAddrs {
+                source: addr(&block[..16], &block[32..34]),
+                destination: addr(&block[16..32], &block[34..36]),
+            }))
+        }
+        // AF_UNSPEC and AF_UNIX carry no usable client address
+        _ => Ok(None),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(bytes: &[u8]) -> (io::Result<Option<ProxiedAddrs>>, Vec<u8>) {
+        let mut reader = bytes;
+        let result = read_header(&mut reader).await;
+        (result, reader.to_vec())
+    }
+
+    fn proxied(source: &str, destination: &str) -> ProxiedAddrs {
+        ProxiedAddrs {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v1() {
+        let (addrs, rest) = parse(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET /").await;
+        assert_eq!(
+            addrs.unwrap(),
+            Some(proxied("192.0.2.1:56324", "198.51.100.1:443"))
+        );
+        assert_eq!(rest, b"GET /");
+
+        let (addrs, _) = parse(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 443\r\n").await;
+        assert_eq!(
+            addrs.unwrap(),
+            Some(proxied("[2001:db8::1]:4000", "[2001:db8::2]:443"))
+        );
+
+        let (addr, rest) = parse(b"PROXY UNKNOWN\r\nGET").await;
+        assert_eq!(addr.unwrap(), None);
+        assert_eq!(rest, b"GET");
+
+        for bad in [
+            &b"GET / HTTP/1.1\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 192.0.2.1 1 2\r\n",
+            b"PROXY TCP4 192.0.2.1 2001:db8::1 1 2\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 99999 443\r\n",
+            &[b'P', b'R', b'O', b'X', b'Y', b' '].repeat(30),
+        ] {
+            assert!(parse(bad).await.0.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v2() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0, 16]);
+        header.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x01, 0xbb]);
+        // A TLV the reader skips
+        header.extend_from_slice(&[0x04, 0, 1, 0]);
+        header.extend_from_slice(b"GET /");
+
+        let (addrs, rest) = parse(&header).await;
+        assert_eq!(
+            addrs.unwrap(),
+            Some(proxied("192.0.2.1:56324", "198.51.100.1:443"))
+        );
+        assert_eq!(rest, b"GET /");
+
+        let mut v6 = V2_SIGNATURE.to_vec();
+        v6.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        v6.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        v6.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        v6.extend_from_slice(&[0x0f, 0xa0, 0x01, 0xbb]);
+        assert_eq!(
+            parse(&v6).await.0.unwrap(),
+            Some(proxied("[2001:db8::1]:4000", "[2001:db8::2]:443"))
+        );
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(parse(&local).await.0.unwrap(), None);
+
+        let mut short = V2_SIGNATURE.to_vec();
+        short.extend_from_slice(&[0x21, 0x21, 0, 12]);
+        short.extend_from_slice(&[0; 12]);
+        assert!(parse(&short).await.0.is_err());
+    }
+}
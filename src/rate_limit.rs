@@ -0,0 +1,535 @@
+//! Request rate limiting middleware.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::{Foton, RateLimit, Req, Res, Route};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!
+//!     // Bursts of 20 per client IP, refilled at 5 requests per second
+//!     app.attach(RateLimit::token_bucket(20, Duration::from_millis(200)));
+//!
+//!     // A stricter limit for one route, keyed by API key
+//!     let mut login = Route::post("/login", |_: Req| async { Res::text("ok") });
+//!     login.attach(RateLimit::sliding_window(5, Duration::from_secs(60)).by_header("x-api-key"));
+//!     app.route(login);
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_trait::async_trait;
+use hyper::header::{self, HeaderValue};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Error, IntoRes, Middleware, Next, Req, Res, Result};
+
+/// How requests are counted against a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Bursts of up to `capacity` requests, with one more allowed every `refill`.
+    TokenBucket {
+        /// Bucket size.
+        capacity: u32,
+        /// Time to regain one request.
+        refill: Duration,
+    },
+    /// At most `limit` requests in any `window`, estimated from the counts of
+    /// the current and previous fixed windows.
+    SlidingWindow {
+        /// Requests allowed per window.
+        limit: u32,
+        /// Window length.
+        window: Duration,
+    },
+}
+
+/// Outcome of counting one request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// Whether the request may proceed.
+    pub allowed: bool,
+    /// The policy's request quota.
+    pub limit: u32,
+    /// Requests left right now.
+    pub remaining: u32,
+    /// Time until the quota is fully available again.
+    pub reset: Duration,
+    /// For rejected requests, time until one would be allowed.
+    pub retry_after: Option<Duration>,
+}
+
+/// Storage for rate limit counters.
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync + 'static {
+    /// Count a request for `key` under `policy`.
+    async fn check(&self, key: &str, policy: &Policy) -> Result<Decision>;
+}
+
+/// In-memory backend spreading keys across independently locked shards.
+///
+/// Idle keys are swept out as their shard is used.
+pub struct MemoryBackend {
+    shards: Box<[Mutex<Shard>]>,
+    hasher: RandomState,
+}
+
+struct Shard {
+    entries: HashMap<String, Entry>,
+    last_sweep: Instant,
+}
+
+enum Entry {
+    Bucket {
+        tokens: f64,
+        updated: Instant,
+    },
+    Window {
+        start: Instant,
+        previous: u32,
+        current: u32,
+    },
+}
+
+/// How often each shard sweeps idle keys.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+impl MemoryBackend {
+    /// Create a backend with 32 shards.
+    pub fn new() -> Self {
+        Self::with_shards(32)
+    }
+
+    /// Create a backend with `shards` shards (at least one).
+    pub fn with_shards(shards: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| {
+                    Mutex::new(Shard {
+                        entries: HashMap::new(),
+                        last_sweep: now,
+                    })
+                })
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn check_at(&self, key: &str, policy: &Policy, now: Instant) -> Decision {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        let mut shard = self.shards[index].lock().unwrap();
+
+        if now.duration_since(shard.last_sweep) >= SWEEP_INTERVAL {
+            shard.entries.retain(|_, entry| !entry.is_idle(policy, now));
+            shard.last_sweep = now;
+        }
+
+        let entry = shard
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Entry::new(policy, now));
+        entry.check(policy, now)
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for MemoryBackend {
+    async fn check(&self, key: &str, policy: &Policy) -> Result<Decision> {
+        Ok(self.check_at(key, policy, Instant::now()))
+    }
+}
+
+impl Entry {
+    fn new(policy: &Policy, now: Instant) -> Self {
+        match *policy {
+            Policy::TokenBucket { capacity, .. } => Entry::Bucket {
+                tokens: capacity as f64,
+                updated: now,
+            },
+            Policy::SlidingWindow { .. } => Entry::Window {
+                start: now,
+                previous: 0,
+                current: 0,
+            },
+        }
+    }
+
+    /// Whether the entry is indistinguishable from a fresh one.
+    fn is_idle(&self, policy: &Policy, now: Instant) -> bool {
+        match (self, *policy) {
+            (Entry::Bucket { updated, .. }, Policy::TokenBucket { capacity, refill }) => {
+                now.duration_since(*updated) >= refill * capacity
+            }
+            (Entry::Window { start, .. }, Policy::SlidingWindow { window, .. }) => {
+                now.duration_since(*start) >= window * 2
+            }
+            _ => true,
+        }
+    }
+
+    fn check(&mut self, policy: &Policy, now: Instant) -> Decision {
+        if !matches!(
+            (&*self, policy),
+            (Entry::Bucket { .. }, Policy::TokenBucket { .. })
+                | (Entry::Window { .. }, Policy::SlidingWindow { .. })
+        ) {
+            *self = Entry::new(policy, now);
+        }
+
+        match (self, *policy) {
+            (Entry::Bucket { tokens, updated }, Policy::TokenBucket { capacity, refill }) => {
+                let refill = refill.as_secs_f64();
+                let elapsed = now.duration_since(*updated).as_secs_f64();
+                *tokens = (*tokens + elapsed / refill).min(capacity as f64);
+                *updated = now;
+
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                Decision {
+                    allowed,
+                    limit: capacity,
+                    remaining: *tokens as u32,
+                    reset: Duration::from_secs_f64((capacity as f64 - *tokens) * refill),
+                    retry_after: (!allowed)
+                        .then(|| Duration::from_secs_f64((1.0 - *tokens) * refill)),
+                }
+            }
+            (
+                Entry::Window {
+                    start,
+                    previous,
+                    current,
+                },
+                Policy::SlidingWindow { limit, window },
+            ) => {
+                let elapsed = now.duration_since(*start);
+                if elapsed >= window * 2 {
+                    *previous = 0;
+                    *current = 0;
+                    *start =
+                        now - Duration::from_secs_f64(elapsed.as_secs_f64() % window.as_secs_f64());
+                } else if elapsed >= window {
+                    *previous = *current;
+                    *current = 0;
+                    *start += window;
+                }
+
+                let w = window.as_secs_f64();
+                let into = now.duration_since(*start).as_secs_f64();
+                let estimate = *previous as f64 * (1.0 - into / w) + *current as f64;
+
+                let allowed = estimate + 1.0 <= limit as f64;
+                if allowed {
+                    *current += 1;
+                }
+                let used = if allowed { estimate + 1.0 } else { estimate };
+
+                let retry_after = (!allowed).then(|| {
+                    let room = limit as f64 - 1.0 - *current as f64;
+                    let secs = if room >= 0.0 && *previous > 0 {
+                        // The previous window's share decays enough before this one ends
+                        w * (1.0 - room / *previous as f64) - into
+                    } else {
+                        // Wait for this window's count to decay in the next one
+                        let next = (1.0 - (limit as f64 - 1.0) / *current as f64).max(0.0);
+                        (w - into) + w * next
+                    };
+                    Duration::from_secs_f64(secs.max(0.0))
+                });
+
+                Decision {
+                    allowed,
+                    limit,
+                    remaining: (limit as f64 - used).max(0.0) as u32,
+                    reset: Duration::from_secs_f64(w - into),
+                    retry_after,
+                }
+            }
+            _ => unreachable!("entry matches policy"),
+        }
+    }
+}
+
+type KeyFn = Arc<dyn Fn(&Req) -> Option<String> + Send + Sync>;
+
+/// Middleware limiting how often each client may make requests.
+///
+/// Requests are keyed by client IP unless configured otherwise; when a key
+/// cannot be determined the client IP is used instead. Responses carry
+/// `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers, and
+/// rejected requests get 429 with `Retry-After`.
+///
+/// Counters are shared by clones and kept per instance, so attaching separate
+/// instances to routes with [`Route::attach`](crate::Route::attach) gives each
+/// route its own limit.
+#[derive(Clone)]
+pub struct RateLimit {
+    policy: Policy,
+    key: KeyFn,
+    backend: Arc<dyn RateLimitBackend>,
+}
+
+impl fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimit {
+    /// Limit with a token bucket: bursts of `capacity`, regaining one request
+    /// every `refill`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero or `refill` is zero.
+    pub fn token_bucket(capacity: u32, refill: Duration) -> Self {
+        assert!(capacity > 0, "rate limit capacity must be positive");
+        assert!(!refill.is_zero(), "rate limit refill must be positive");
+        Self::new(Policy::TokenBucket { capacity, refill })
+    }
+
+    /// Limit with a sliding window of `limit` requests per `window`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero or `window` is zero.
+    pub fn sliding_window(limit: u32, window: Duration) -> Self {
+        assert!(limit > 0, "rate limit must be positive");
+        assert!(!window.is_zero(), "rate limit window must be positive");
+        Self::new(Policy::SlidingWindow { limit, window })
+    }
+
+    fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            key: Arc::new(|_| None),
+            backend: Arc::new(MemoryBackend::new()),
+        }
+    }
+
+    /// Key by client IP (the default), as resolved by
+    /// [`ClientIp`](crate::ClientIp).
+    pub fn by_ip(mut self) -> Self {
+        self.key = Arc::new(|_| None);
+        self
+    }
+
+    /// Key by the value of a request header.
+    pub fn by_header(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        self.key = Arc::new(move |req| req.header(&name).map(|v| format!("header:{}", v)));
+        self
+    }
+
+    /// Key by the authenticated user an earlier middleware stored in the
+    /// request extensions as a `U`.
+    pub fn by_user<U>(mut self) -> Self
+    where
+        U: fmt::Display + Send + Sync + 'static,
+    {
+        self.key = Arc::new(|req| req.extensions().get::<U>().map(|u| format!("user:{}", u)));
+        self
+    }
+
+    /// Key with a closure.
+    pub fn by<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Req) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(move |req| key(req).map(|k| format!("key:{}", k)));
+        self
+    }
+
+    /// Store counters in `backend` instead of a fresh [`MemoryBackend`].
+    pub fn backend<B: RateLimitBackend>(mut self, backend: B) -> Self {
+        self.backend = Arc::new(backend);
+        self
+    }
+
+    fn key(&self, req: &Req) -> String {
+        (self.key)(req).unwrap_or_else(|| match req.client_ip() {
+            Some(ip) => format!("ip:{}", ip),
+            None => "ip:unknown".to_string(),
+        })
+    }
+}
+
+/// Headers describing `decision`, in whole seconds rounded up.
+fn headers(decision: &Decision) -> [(&'static str, String); 3] {
+    [
+        ("ratelimit-limit", decision.limit.to_string()),
+        ("ratelimit-remaining", decision.remaining.to_string()),
+        ("ratelimit-reset", seconds(decision.reset).to_string()),
+    ]
+}
+
+fn seconds(duration: Duration) -> u64 {
+    duration.as_secs_f64().ceil() as u64
+}
+
+#[async_trait]
+impl<S> Middleware<S> for RateLimit
+where
+    S: Send + Sync + 'static,
+{
+    async fn handle(&self, req: Req, _state: Arc<S>, next: Next<S>) -> Res {
+        let key = self.key(&req);
+        let decision = match self.backend.check(&key, &self.policy).await {
+            Ok(decision) => decision,
+            Err(e) => return e.into_res(),
+        };
+
+        if !decision.allowed {
+            let retry_after = seconds(decision.retry_after.unwrap_or(decision.reset)).max(1);
+            // Headers on the error response survive a custom ErrorHandler
+            let mut res = Error::too_many_requests("Too many requests").into_res();
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            for (name, value) in headers(&decision) {
+                if let Ok(value) = value.parse() {
+                    res.headers_mut().insert(name, value);
+                }
+            }
+            return res;
+        }
+
+        let mut res = next.run(req).await;
+        // An inner, more specific limiter has already set its headers
+        if !res.headers().contains_key("ratelimit-limit") {
+            for (name, value) in headers(&decision) {
+                if let Ok(value) = value.parse() {
+                    res.headers_mut().insert(name, value);
+                }
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Foton, Route};
+
+    fn secs(duration: Option<Duration>) -> f64 {
+        duration.unwrap().as_secs_f64()
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let backend = MemoryBackend::with_shards(4);
+        let policy = Policy::TokenBucket {
+            capacity: 2,
+            refill: Duration::from_secs(10),
+        };
+        let t0 = Instant::now();
+
+        let first = backend.check_at("a", &policy, t0);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert_eq!(first.reset, Duration::from_secs(10));
+        assert!(backend.check_at("a", &policy, t0).allowed);
+
+        let denied = backend.check_at("a", &policy, t0 + Duration::from_secs(4));
+        assert!(!denied.allowed);
+        assert!((secs(denied.retry_after) - 6.0).abs() < 1e-6);
+
+        // Other keys are independent
+        assert!(backend.check_at("b", &policy, t0).allowed);
+
+        let refilled = backend.check_at("a", &policy, t0 + Duration::from_secs(10));
+        assert!(refilled.allowed);
+        assert_eq!(refilled.remaining, 0);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let backend = MemoryBackend::new();
+        let policy = Policy::SlidingWindow {
+            limit: 4,
+            window: Duration::from_secs(10),
+        };
+        let t0 = Instant::now();
+
+        for _ in 0..4 {
+            assert!(backend.check_at("a", &policy, t0).allowed);
+        }
+        let denied = backend.check_at("a", &policy, t0 + Duration::from_secs(5));
+        assert!(!denied.allowed);
+        assert_eq!(denied.reset, Duration::from_secs(5));
+        // 4 requests decay to 3 a quarter into the next window
+        assert!((secs(denied.retry_after) - 7.5).abs() < 1e-6);
+
+        // Halfway through the next window the previous 4 count as 2
+        let later = backend.check_at("a", &policy, t0 + Duration::from_secs(15));
+        assert!(later.allowed);
+        assert_eq!(later.remaining, 1);
+
+        // Two idle windows reset the count
+        let fresh = backend.check_at("a", &policy, t0 + Duration::from_secs(31));
+        assert_eq!(fresh.remaining, 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_middleware() {
+        let mut app = Foton::new();
+        app.attach(RateLimit::token_bucket(2, Duration::from_secs(60)));
+        let mut route = Route::get("/strict", |_: Req| async { "strict" });
+        route.attach(RateLimit::sliding_window(1, Duration::from_secs(60)).by_header("x-key"));
+        app.route(route);
+        app.get("/", |_: Req| async { "ok" });
+        app.set_error_handler(|error: Error| match error {
+            Error::Status(429, _) => Res::builder().status(429).text("slow down"),
+            other => other.into_res(),
+        });
+        let client = app.into_test_client();
+
+        let from = |ip: [u8; 4]| client.get("/").remote_addr((ip, 4000));
+        let res = from([10, 0, 0, 1]).send().await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.headers()["ratelimit-limit"], "2");
+        assert_eq!(res.headers()["ratelimit-remaining"], "1");
+        assert_eq!(res.headers()["ratelimit-reset"], "60");
+        from([10, 0, 0, 1]).send().await;
+
+        let res = from([10, 0, 0, 1]).send().await;
+        assert_eq!(res.status_code(), 429);
+        assert_eq!(res.headers()["retry-after"], "60");
+        assert_eq!(res.headers()["ratelimit-remaining"], "0");
+        assert_eq!(res.into_text().await.unwrap(), "slow down");
+        assert_eq!(from([10, 0, 0, 2]).send().await.status_code(), 200);
+
+        // The route's own limit applies inside the global one
+        let strict = |ip: u8, key: &str| {
+            client
+                .get("/strict")
+                .remote_addr(([10, 0, 1, ip], 4000))
+                .header("x-key", key)
+                .send()
+        };
+        let res = strict(1, "a").await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.headers()["ratelimit-limit"], "1");
+        assert_eq!(strict(2, "a").await.status_code(), 429);
+        assert_eq!(strict(3, "b").await.status_code(), 200);
+    }
+}
@@ -1,14 +1,28 @@
 //! HTTP request with lock-free body consumption.
 
-use bytes::Bytes;
-use http_body_util::BodyExt;
-use hyper::{Method, Request, Uri, body::Incoming, header};
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Body;
+use hyper::{Method, Request, Uri, Version, header};
 use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio::sync::OnceCell;
+use tokio::time::{Instant, Sleep};
+use tokio_util::io::StreamReader;
 
+use crate::connect_info::{Cidr, ConnectInfo, resolve_client_ip};
 use crate::extensions::Extensions;
 use crate::{Error, Result};
 
+/// Boxed body type for requests.
+pub type ReqBody = http_body_util::combinators::BoxBody<Bytes, Error>;
+
 #[cfg(feature = "websocket")]
 use hyper::upgrade::OnUpgrade;
 
@@ -16,35 +30,60 @@ use hyper::upgrade::OnUpgrade;
 pub struct Req {
     method: Method,
     uri: Uri,
+    version: Version,
     headers: header::HeaderMap,
     body_cell: OnceCell<Bytes>,
-    incoming: Option<Incoming>,
+    incoming: Option<ReqBody>,
     path_params: HashMap<String, String>,
+    ordered_params: Vec<(String, String)>,
+    path_error_status: u16,
     extensions: Extensions,
+    connect_info: Option<ConnectInfo>,
+    trusted_proxies: Arc<[Cidr]>,
     body_limit: Option<usize>,
+    body_deadline: Option<Instant>,
     #[cfg(feature = "websocket")]
     upgrade: Option<OnUpgrade>,
 }
 
 impl Req {
     /// Create from hyper request.
-    pub fn from_hyper(
-        #[cfg_attr(not(feature = "websocket"), allow(unused_mut))] mut req: Request<Incoming>,
-    ) -> Self {
+    ///
+    /// Accepts any body type, so requests can be built from `Incoming` or from
+    /// in-memory bodies such as `Full<Bytes>` in tests. With the `compression`
+    /// feature, bodies with a `Content-Encoding` are decoded transparently.
+    pub fn from_hyper<B>(
+        #[cfg_attr(not(feature = "websocket"), allow(unused_mut))] mut req: Request<B>,
+    ) -> Self
+    where
+        B: Body<Data = Bytes> + Send + Sync + 'static,
+        B::Error: Into<Error>,
+    {
         #[cfg(feature = "websocket")]
         let upgrade = Some(hyper::upgrade::on(&mut req));
 
-        let (parts, body) = req.into_parts();
+        #[cfg_attr(not(feature = "compression"), allow(unused_mut))]
+        let (mut parts, body) = req.into_parts();
+        let body = body.map_err(Into::into).boxed();
+
+        #[cfg(feature = "compression")]
+        let body = crate::decompression::decode_body(&mut parts.headers, body);
 
         Self {
             method: parts.method,
             uri: parts.uri,
+            version: parts.version,
             headers: parts.headers,
             body_cell: OnceCell::new(),
             incoming: Some(body),
             path_params: HashMap::new(),
+            ordered_params: Vec::new(),
+            path_error_status: 400,
             extensions: Extensions::new(),
+            connect_info: None,
+            trusted_proxies: Arc::new([]),
             body_limit: None,
+            body_deadline: None,
             #[cfg(feature = "websocket")]
             upgrade,
         }
@@ -61,6 +100,28 @@ impl Req {
         self.body_limit = limit;
     }
 
+    /// Set the status returned when `Path` fails to deserialize.
+    pub(crate) fn set_path_error_status(&mut self, status: u16) {
+        self.path_error_status = status;
+    }
+
+    pub(crate) fn path_error_status(&self) -> u16 {
+        self.path_error_status
+    }
+
+    pub(crate) fn set_connect_info(&mut self, info: Option<ConnectInfo>) {
+        self.connect_info = info;
+    }
+
+    pub(crate) fn set_trusted_proxies(&mut self, proxies: Arc<[Cidr]>) {
+        self.trusted_proxies = proxies;
+    }
+
+    /// Set time allowed for the body upload, measured from now.
+    pub(crate) fn set_body_timeout(&mut self, timeout: Option<Duration>) {
+        self.body_deadline = timeout.map(|t| Instant::now() + t);
+    }
+
     /// Get HTTP method.
     #[inline]
     pub fn method(&self) -> &Method {
@@ -73,6 +134,38 @@ impl Req {
         &self.uri
     }
 
+    /// Get HTTP version.
+    #[inline]
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Address of the client, or `None` for requests not received over a
+    /// socket. With the PROXY protocol this is the address the proxy reported.
+    #[inline]
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.connect_info.map(|info| info.remote_addr)
+    }
+
+    /// Addresses of the connection, or `None` for requests not received over
+    /// a socket.
+    #[inline]
+    pub fn connect_info(&self) -> Option<ConnectInfo> {
+        self.connect_info
+    }
+
+    /// Client IP, following forwarding headers added by trusted proxies.
+    ///
+    /// See [`ClientIp`](crate::ClientIp).
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        let peer = self.remote_addr()?.ip();
+        Some(resolve_client_ip(
+            peer,
+            &self.headers,
+            &self.trusted_proxies,
+        ))
+    }
+
     /// Get request path.
     #[inline]
     pub fn path(&self) -> &str {
@@ -125,48 +218,71 @@ impl Req {
     pub async fn body(&mut self) -> Result<&Bytes> {
         self.body_cell
             .get_or_try_init(|| async {
-                let incoming = self
-                    .incoming
-                    .take()
-                    .ok_or_else(|| Error::internal("Request body already consumed"))?;
-
-                // Check Content-Length header against limit
-                if let Some(limit) = self.body_limit {
-                    if let Some(content_length) = self.headers.get(header::CONTENT_LENGTH) {
-                        if let Ok(length_str) = content_length.to_str() {
-                            if let Ok(length) = length_str.parse::<usize>() {
-                                if length > limit {
-                                    return Err(Error::payload_too_large(&format!(
-                                        "Request body size {} exceeds limit of {}",
-                                        length, limit
-                                    )));
-                                }
-                            }
-                        }
-                    }
+                let mut stream = Self::take_body_stream(
+                    &mut self.incoming,
+                    &self.headers,
+                    self.body_limit,
+                    self.body_deadline,
+                )?;
+
+                let mut body = BytesMut::new();
+                while let Some(chunk) = stream.next().await {
+                    body.extend_from_slice(&chunk?);
                 }
+                Ok(body.freeze())
+            })
+            .await
+    }
 
-                let collected = incoming
-                    .collect()
-                    .await
-                    .map_err(|e| Error::Custom(format!("Failed to read body: {}", e)))?;
-
-                let body_bytes = collected.to_bytes();
-
-                // Check actual body size against limit
-                if let Some(limit) = self.body_limit {
-                    if body_bytes.len() > limit {
-                        return Err(Error::payload_too_large(&format!(
-                            "Request body size {} exceeds limit of {}",
-                            body_bytes.len(),
-                            limit
-                        )));
-                    }
+    /// Consume body as a stream of chunks.
+    ///
+    /// The body limit and upload timeout are enforced as chunks arrive. If
+    /// the body was already buffered by [`Req::body`], the buffered bytes are
+    /// streamed instead.
+    pub fn body_stream(&mut self) -> Result<BodyStream> {
+        if let Some(body) = self.body_cell.get() {
+            return Ok(BodyStream::new(
+                Full::new(body.clone()).map_err(Into::into).boxed(),
+                None,
+                None,
+            ));
+        }
+
+        Self::take_body_stream(
+            &mut self.incoming,
+            &self.headers,
+            self.body_limit,
+            self.body_deadline,
+        )
+    }
+
+    fn take_body_stream(
+        incoming: &mut Option<ReqBody>,
+        headers: &header::HeaderMap,
+        limit: Option<usize>,
+        deadline: Option<Instant>,
+    ) -> Result<BodyStream> {
+        let body = incoming
+            .take()
+            .ok_or_else(|| Error::internal("Request body already consumed"))?;
+
+        // Check Content-Length header against limit
+        if let Some(limit) = limit {
+            if let Some(length) = headers
+                .get(header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<usize>().ok())
+            {
+                if length > limit {
+                    return Err(Error::payload_too_large(format!(
+                        "Request body size {} exceeds limit of {}",
+                        length, limit
+                    )));
                 }
+            }
+        }
 
-                Ok(body_bytes)
-            })
-            .await
+        Ok(BodyStream::new(body, limit, deadline))
     }
 
     /// Get Content-Type header.
@@ -195,9 +311,15 @@ impl Req {
         &mut self.extensions
     }
 
-    #[inline]
-    pub(crate) fn set_path_params(&mut self, params: HashMap<String, String>) {
-        self.path_params = params;
+    /// Set path parameters, in route order.
+    pub(crate) fn set_path_params(&mut self, params: Vec<(String, String)>) {
+        self.path_params = params.iter().cloned().collect();
+        self.ordered_params = params;
+    }
+
+    /// Path parameters in route order, still percent-encoded.
+    pub(crate) fn ordered_params(&self) -> &[(String, String)] {
+        &self.ordered_params
     }
 
     /// Check if request is WebSocket upgrade (GET with upgrade headers).
@@ -225,3 +347,147 @@ impl Req {
         self.header("sec-websocket-key")
     }
 }
+
+/// Request body as a stream of chunks.
+///
+/// Obtained from [`Req::body_stream`] or used directly as an extractor.
+pub struct BodyStream {
+    body: ReqBody,
+    limit: Option<usize>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    read: usize,
+    done: bool,
+}
+
+impl BodyStream {
+    fn new(body: ReqBody, limit: Option<usize>, deadline: Option<Instant>) -> Self {
+        Self {
+            body,
+            limit,
+            deadline: deadline.map(|d| Box::pin(tokio::time::sleep_until(d))),
+            read: 0,
+            done: false,
+        }
+    }
+
+    /// Convert into an [`AsyncRead`](tokio::io::AsyncRead).
+    ///
+    /// Body errors surface as [`std::io::Error`]s of kind `Other`.
+    pub fn into_reader(self) -> impl tokio::io::AsyncRead + Send + Unpin {
+        StreamReader::new(self.map_err(std::io::Error::other))
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            let frame = match Pin::new(&mut this.body).poll_frame(cx) {
+                Poll::Ready(frame) => frame,
+                Poll::Pending => {
+                    if let Some(deadline) = &mut this.deadline {
+                        if deadline.as_mut().poll(cx).is_ready() {
+                            this.done = true;
+                            return Poll::Ready(Some(Err(Error::request_timeout(
+                                "Request body not received in time",
+                            ))));
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            };
+
+            let data = match frame {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => data,
+                    // Skip trailers
+                    Err(_) => continue,
+                },
+                Some(Err(e)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(match e {
+                        Error::Hyper(e) => Error::Custom(format!("Failed to read body: {}", e)),
+                        e => e,
+                    })));
+                }
+                None => {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+            };
+
+            this.read += data.len();
+            if let Some(limit) = this.limit {
+                if this.read > limit {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(Error::payload_too_large(format!(
+                        "Request body size exceeds limit of {}",
+                        limit
+                    )))));
+                }
+            }
+
+            return Poll::Ready(Some(Ok(data)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+    use tokio::io::AsyncReadExt;
+
+    fn chunked_req(chunks: &[&'static str]) -> Req {
+        let frames: Vec<_> = chunks
+            .iter()
+            .map(|c| {
+                Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from_static(c.as_bytes())))
+            })
+            .collect();
+        let req = Request::new(StreamBody::new(futures_util::stream::iter(frames)));
+        Req::from_hyper(req)
+    }
+
+    #[tokio::test]
+    async fn test_body_stream_enforces_limit_incrementally() {
+        let mut req = chunked_req(&["abc", "def", "ghi"]);
+        req.set_body_limit(Some(5));
+
+        let mut stream = req.body_stream().unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "abc");
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(err.status_code(), 413);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_body_stream_reader() {
+        let mut req = chunked_req(&["hello ", "world"]);
+        let mut text = String::new();
+        req.body_stream()
+            .unwrap()
+            .into_reader()
+            .read_to_string(&mut text)
+            .await
+            .unwrap();
+        assert_eq!(text, "hello world");
+        assert!(req.body_stream().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_body_stream_after_buffering() {
+        let mut req = chunked_req(&["a", "b"]);
+        assert_eq!(req.body().await.unwrap(), "ab");
+
+        let chunks: Vec<_> = req.body_stream().unwrap().try_collect().await.unwrap();
+        assert_eq!(chunks, vec![Bytes::from("ab")]);
+    }
+}
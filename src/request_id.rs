@@ -0,0 +1,317 @@
+//! Request ID middleware and extractor.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::request_id::IdFormat;
+//! use foton::{Error, Foton, RequestId, Res};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!     // Attach first, so every response and error carries the ID
+//!     app.attach(RequestId::middleware().format(IdFormat::UuidV7));
+//!
+//!     app.set_error_handler(|error: Error| {
+//!         let id = RequestId::current().map(|id| id.to_string());
+//!         Res::builder()
+//!             .status(error.status_code())
+//!             .json(&serde_json::json!({ "error": error.message(), "request_id": id }))
+//!     });
+//!
+//!     app.get("/", |id: RequestId| async move { Res::text(format!("request {}", id)) });
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_trait::async_trait;
+use hyper::header::{HeaderName, HeaderValue};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Error, FromRequest, Middleware, Next, Req, Res, Result};
+
+tokio::task_local! {
+    // Scoped around the whole request, so the ID outlives the middleware
+    // for the final rendering of errors
+    static CURRENT: OnceLock<RequestId>;
+}
+
+/// Run `fut`, the handling of one request, with room for its ID.
+pub(crate) async fn scope<F: Future>(fut: F) -> F::Output {
+    CURRENT.scope(OnceLock::new(), fut).await
+}
+
+/// Identifier of the current request, set by [`RequestIdMiddleware`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Middleware assigning IDs, with the `x-request-id` header and UUID v4 IDs.
+    pub fn middleware() -> RequestIdMiddleware {
+        RequestIdMiddleware {
+            header: HeaderName::from_static("x-request-id"),
+            format: IdFormat::UuidV4,
+            trust_inbound: true,
+        }
+    }
+
+    /// ID of the request being handled by the current task.
+    ///
+    /// Available to handlers, middleware inside the request ID middleware,
+    /// logging and the [`ErrorHandler`].
+    pub fn current() -> Option<RequestId> {
+        CURRENT.try_with(|id| id.get().cloned()).ok().flatten()
+    }
+
+    /// The ID as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for RequestId
+where
+    S: Send + Sync + 'static,
+{
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        req.extensions()
+            .get::<RequestId>()
+            .cloned()
+            .ok_or_else(|| Error::internal("RequestId used without its middleware"))
+    }
+}
+
+/// Format of generated request IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdFormat {
+    /// Random UUID, such as `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+    UuidV4,
+    /// Time-ordered UUID.
+    UuidV7,
+    /// Time-ordered ULID, such as `01ARZ3NDEKTSV4RRFFQ69G5FAV`.
+    Ulid,
+}
+
+impl IdFormat {
+    fn generate(self) -> String {
+        match self {
+            IdFormat::UuidV4 => uuid::Uuid::new_v4().to_string(),
+            IdFormat::UuidV7 => uuid::Uuid::now_v7().to_string(),
+            IdFormat::Ulid => ulid(),
+        }
+    }
+}
+
+/// Middleware giving each request an ID.
+///
+/// An ID sent by the client in the configured header is reused if it is at
+/// most 128 visible ASCII characters; otherwise a new one is generated. The
+/// ID is stored in the request extensions, echoed in the response header and
+/// available through [`RequestId::current`] while the request is handled,
+/// including when the [`ErrorHandler`](crate::ErrorHandler) renders errors.
+#[derive(Debug, Clone)]
+pub struct RequestIdMiddleware {
+    header: HeaderName,
+    format: IdFormat,
+    trust_inbound: bool,
+}
+
+impl RequestIdMiddleware {
+    /// Read and echo the ID in `name` instead of `x-request-id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid header name.
+    pub fn header(mut self, name: &str) -> Self {
+        self.header = HeaderName::from_bytes(name.as_bytes()).expect("invalid request ID header");
+        self
+    }
+
+    /// Format of generated IDs.
+    pub fn format(mut self, format: IdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Whether to reuse IDs sent by clients. Enabled by default; disable when
+    /// clients are not trusted to pick unique IDs.
+    pub fn trust_inbound(mut self, trust: bool) -> Self {
+        self.trust_inbound = trust;
+        self
+    }
+}
+
+#[async_trait]
+impl<S> Middleware<S> for RequestIdMiddleware
+where
+    S: Send + Sync + 'static,
+{
+    async fn handle(&self, mut req: Req, _state: Arc<S>, next: Next<S>) -> Res {
+        let inbound = self
+            .trust_inbound
+            .then(|| req.headers().get(&self.header))
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| is_valid_id(id))
+            .map(str::to_string);
+        let id = RequestId(inbound.unwrap_or_else(|| self.format.generate()));
+
+        req.extensions_mut().insert(id.clone());
+        let value = HeaderValue::from_str(&id.0).ok();
+        let _ = CURRENT.try_with(|current| current.set(id));
+
+        let mut res = next.run(req).await;
+        if let Some(value) = value {
+            res.headers_mut().insert(self.header.clone(), value);
+        }
+        res
+    }
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Crockford base32 alphabet used by ULIDs.
+const ULID_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// 48-bit millisecond timestamp and 80 random bits, as 26 base32 characters.
+fn ulid() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    // Bytes of a v4 UUID outside its version and variant bits are random
+    let random = uuid::Uuid::new_v4().into_bytes();
+    let mut value = ((millis & 0xffff_ffff_ffff) as u128) << 80;
+    for (i, byte) in random[..6].iter().chain(&random[9..13]).enumerate() {
+        value |= (*byte as u128) << (72 - 8 * i);
+    }
+
+    (0..26)
+        .rev()
+        .map(|i| ULID_ALPHABET[((value >> (5 * i)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Foton, IntoRes};
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let mut app = Foton::new();
+        app.attach(RequestId::middleware());
+        app.get("/", |id: RequestId| async move { id.to_string() });
+        let client = app.into_test_client();
+
+        let res = client
+            .get("/")
+            .header("x-request-id", "abc-123")
+            .send()
+            .await;
+        assert_eq!(res.headers()["x-request-id"], "abc-123");
+        assert_eq!(res.into_text().await.unwrap(), "abc-123");
+
+        let res = client
+            .get("/")
+            .header("x-request-id", "has space")
+            .send()
+            .await;
+        let id = res.headers()["x-request-id"].to_str().unwrap().to_string();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(res.into_text().await.unwrap(), id);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_in_errors() {
+        let mut app = Foton::new();
+        app.attach(
+            RequestId::middleware()
+                .header("x-trace")
+                .format(IdFormat::Ulid)
+                .trust_inbound(false),
+        );
+        app.set_error_handler(|error: Error| {
+            let id = RequestId::current().unwrap();
+            Res::builder()
+                .status(error.status_code())
+                .text(format!("{} {}", error.message(), id))
+        });
+        let client = app.into_test_client();
+
+        let res = client
+            .get("/missing")
+            .header("x-trace", "spoofed")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 404);
+        let id = res.headers()["x-trace"].to_str().unwrap().to_string();
+        assert_eq!(id.len(), 26);
+        assert!(id.bytes().all(|b| ULID_ALPHABET.contains(&b)));
+        assert_eq!(
+            res.into_text().await.unwrap(),
+            format!("Route not found {}", id)
+        );
+        assert!(RequestId::current().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_outer_middleware_sees_errors() {
+        let mut app = Foton::new();
+        // Outer middleware, such as compression, must still see the error
+        app.attach(crate::from_fn(
+            |req: Req, _state: Arc<()>, next: Next| async move {
+                let mut res = next.run(req).await;
+                let rendered = if res.error().is_some() { "no" } else { "yes" };
+                res.headers_mut()
+                    .insert("x-rendered", HeaderValue::from_static(rendered));
+                res
+            },
+        ));
+        app.attach(RequestId::middleware());
+        app.attach(crate::from_fn(
+            |_req: Req, _state: Arc<()>, _next: Next| async move {
+                Error::forbidden("nope").into_res()
+            },
+        ));
+        app.set_error_handler(|error: Error| {
+            Res::builder().status(error.status_code()).text(format!(
+                "{} {}",
+                error.message(),
+                RequestId::current().unwrap()
+            ))
+        });
+        let client = app.into_test_client();
+
+        let res = client
+            .get("/")
+            .header("x-request-id", "abc-123")
+            .send()
+            .await;
+        assert_eq!(res.status_code(), 403);
+        assert_eq!(res.headers()["x-rendered"], "no");
+        assert_eq!(res.headers()["x-request-id"], "abc-123");
+        assert_eq!(res.into_text().await.unwrap(), "nope abc-123");
+    }
+
+    #[test]
+    fn test_ulid_order() {
+        let first = ulid();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(ulid() > first);
+    }
+}
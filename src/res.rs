@@ -1,11 +1,13 @@
 //! HTTP response.
 
 use bytes::Bytes;
+use cookie::Cookie;
 use futures_util::TryStreamExt;
 use http_body_util::{BodyExt, Full, StreamBody as HttpStreamBody};
 use hyper::body::Frame;
 use hyper::{Response, StatusCode, header};
 use serde::Serialize;
+use serde::de::DeserializeOwned;
 use std::future::Future;
 use std::path::Path;
 use tokio::fs::File;
@@ -18,6 +20,7 @@ use base64::{Engine as _, engine::general_purpose};
 #[cfg(feature = "websocket")]
 use sha1::{Digest, Sha1};
 
+use crate::file::FileMeta;
 use crate::{Error, Result};
 
 /// Boxed body type for responses.
@@ -53,6 +56,7 @@ impl StreamSender {
 /// HTTP response.
 pub struct Res {
     inner: Response<BoxBody>,
+    error: Option<Error>,
     #[cfg(feature = "websocket")]
     ws_callback: Option<crate::websocket::WebSocketHandler>,
 }
@@ -61,11 +65,9 @@ impl Res {
     /// Create empty 200 response.
     #[inline]
     pub fn new() -> Self {
-        Self {
-            inner: Response::new(Full::new(Bytes::new()).map_err(|e| match e {}).boxed()),
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Self::from_hyper(Response::new(
+            Full::new(Bytes::new()).map_err(|e| match e {}).boxed(),
+        ))
     }
 
     /// Wrap hyper response.
@@ -73,6 +75,7 @@ impl Res {
     pub fn from_hyper(inner: Response<BoxBody>) -> Self {
         Self {
             inner,
+            error: None,
             #[cfg(feature = "websocket")]
             ws_callback: None,
         }
@@ -84,6 +87,25 @@ impl Res {
         self.inner
     }
 
+    /// Get the error this response was rendered from, if any.
+    #[inline]
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    /// Attach the error this response was rendered from.
+    #[inline]
+    pub(crate) fn with_error(mut self, error: Error) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Take the attached error, if any.
+    #[inline]
+    pub(crate) fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
     /// Get WebSocket callback if present.
     #[cfg(feature = "websocket")]
     #[inline]
@@ -98,7 +120,7 @@ impl Res {
     /// # Example
     ///
     /// ```rust,no_run
-    /// use rust_api::{Res, StreamSender};
+    /// use foton::{Res, StreamSender};
     ///
     /// async fn handler() -> Res {
     ///     Res::stream(|mut tx: StreamSender| async move {
@@ -122,17 +144,21 @@ impl Res {
         let stream = ReceiverStream::new(rx).map_ok(Frame::data);
         let body = HttpStreamBody::new(stream).boxed();
 
-        Self {
-            inner: Response::new(body),
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Self::from_hyper(Response::new(body))
     }
 
     /// Stream file from disk. Returns 404 if not found.
     ///
+    /// Content-Type is inferred from the file extension. Content-Length, ETag
+    /// and Last-Modified are set from file metadata, and the request's
+    /// `Range`, `If-Range`, `If-None-Match` and `If-Modified-Since` headers are
+    /// honored with 206, 416 and 304 responses.
+    ///
     /// ```rust,no_run
-    /// Res::file("index.html").await.header("content-type", "text/html")
+    /// # use foton::Res;
+    /// # async fn handler() -> Res {
+    /// Res::file("index.html").await
+    /// # }
     /// ```
     pub async fn file(path: impl AsRef<Path>) -> Self {
         let path = path.as_ref();
@@ -143,19 +169,39 @@ impl Res {
                 return Self::builder().status(404).text("File not found");
             }
         };
+        let meta = match file.metadata().await {
+            Ok(metadata) if metadata.is_file() => FileMeta::new(path.to_path_buf(), &metadata),
+            _ => return Self::builder().status(404).text("File not found"),
+        };
 
         let reader_stream = ReaderStream::new(file);
         let stream_body =
             HttpStreamBody::new(reader_stream.map_ok(Frame::data).map_err(Error::from));
         let boxed_body = stream_body.boxed();
 
-        let res = Response::new(boxed_body);
-
-        Self {
-            inner: res,
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
+        let mut res = Response::new(boxed_body);
+        let headers = res.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(crate::mime::from_path(path)),
+        );
+        headers.insert(header::CONTENT_LENGTH, meta.len().into());
+        headers.insert(
+            header::ACCEPT_RANGES,
+            header::HeaderValue::from_static("bytes"),
+        );
+        if let Ok(etag) = header::HeaderValue::from_str(meta.etag()) {
+            headers.insert(header::ETAG, etag);
         }
+        if let Some(modified) = meta
+            .last_modified()
+            .and_then(|m| header::HeaderValue::from_str(&m).ok())
+        {
+            headers.insert(header::LAST_MODIFIED, modified);
+        }
+        res.extensions_mut().insert(meta);
+
+        Self::from_hyper(res)
     }
 
     /// Text response.
@@ -168,11 +214,7 @@ impl Res {
         );
         res.headers_mut()
             .insert(header::CONTENT_TYPE, CONTENT_TYPE_TEXT.clone());
-        Self {
-            inner: res,
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Self::from_hyper(res)
     }
 
     /// HTML response.
@@ -185,11 +227,7 @@ impl Res {
         );
         res.headers_mut()
             .insert(header::CONTENT_TYPE, CONTENT_TYPE_HTML.clone());
-        Self {
-            inner: res,
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Self::from_hyper(res)
     }
 
     /// JSON response (serializes to Vec<u8> directly).
@@ -203,11 +241,7 @@ impl Res {
                 );
                 res.headers_mut()
                     .insert(header::CONTENT_TYPE, CONTENT_TYPE_JSON.clone());
-                Self {
-                    inner: res,
-                    #[cfg(feature = "websocket")]
-                    ws_callback: None,
-                }
+                Self::from_hyper(res)
             }
             Err(e) => {
                 let error_msg = format!(r#"{{"error": "JSON serialization failed: {}"}}"#, e);
@@ -219,11 +253,7 @@ impl Res {
                 *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
                 res.headers_mut()
                     .insert(header::CONTENT_TYPE, CONTENT_TYPE_JSON.clone());
-                Self {
-                    inner: res,
-                    #[cfg(feature = "websocket")]
-                    ws_callback: None,
-                }
+                Self::from_hyper(res)
             }
         }
     }
@@ -232,11 +262,7 @@ impl Res {
     pub fn status(code: u16) -> Self {
         let mut res = Response::new(Full::new(Bytes::new()).map_err(|e| match e {}).boxed());
         *res.status_mut() = StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-        Self {
-            inner: res,
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Self::from_hyper(res)
     }
 
     /// Create builder.
@@ -263,7 +289,7 @@ impl Res {
         hasher.update(websocket_key.as_bytes());
         hasher.update(WEBSOCKET_GUID.as_bytes());
         let hash = hasher.finalize();
-        let accept_key = general_purpose::STANDARD.encode(&hash);
+        let accept_key = general_purpose::STANDARD.encode(hash);
 
         let mut res = Response::new(Full::new(Bytes::new()).map_err(|e| match e {}).boxed());
         *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
@@ -284,6 +310,7 @@ impl Res {
 
         Self {
             inner: res,
+            error: None,
             ws_callback: Some(std::sync::Arc::new(move |ws| Box::pin(handler(ws)))),
         }
     }
@@ -305,6 +332,21 @@ impl Res {
         self
     }
 
+    /// Add a `Set-Cookie` header. Name and value are percent-encoded.
+    pub fn cookie(mut self, cookie: impl Into<Cookie<'static>>) -> Self {
+        append_cookie(self.inner.headers_mut(), cookie.into());
+        self
+    }
+
+    /// Tell the client to delete a cookie.
+    ///
+    /// Path and domain must match those the cookie was set with.
+    pub fn remove_cookie(self, cookie: impl Into<Cookie<'static>>) -> Self {
+        let mut cookie = cookie.into();
+        cookie.make_removal();
+        self.cookie(cookie)
+    }
+
     /// Get mutable headers.
     #[inline]
     pub fn headers_mut(&mut self) -> &mut header::HeaderMap {
@@ -316,6 +358,24 @@ impl Res {
     pub fn headers(&self) -> &header::HeaderMap {
         self.inner.headers()
     }
+
+    /// Collect the response body into bytes.
+    pub async fn into_bytes(self) -> Result<Bytes> {
+        Ok(self.inner.into_body().collect().await?.to_bytes())
+    }
+
+    /// Collect the response body as UTF-8 text.
+    pub async fn into_text(self) -> Result<String> {
+        let bytes = self.into_bytes().await?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| Error::Custom(format!("Response body is not valid UTF-8: {}", e)))
+    }
+
+    /// Collect and deserialize the response body as JSON.
+    pub async fn into_json<T: DeserializeOwned>(self) -> Result<T> {
+        let bytes = self.into_bytes().await?;
+        serde_json::from_slice(&bytes).map_err(|e| Error::Json(e.to_string()))
+    }
 }
 
 impl Default for Res {
@@ -356,6 +416,12 @@ impl ResBuilder {
         self
     }
 
+    /// Add a `Set-Cookie` header. Name and value are percent-encoded.
+    pub fn cookie(mut self, cookie: impl Into<Cookie<'static>>) -> Self {
+        append_cookie(&mut self.headers, cookie.into());
+        self
+    }
+
     /// Build text response.
     pub fn text(mut self, body: impl Into<String>) -> Res {
         let body_str = body.into();
@@ -372,11 +438,7 @@ impl ResBuilder {
         }
 
         *res.headers_mut() = self.headers;
-        Res {
-            inner: res,
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Res::from_hyper(res)
     }
 
     /// Build HTML response.
@@ -395,11 +457,7 @@ impl ResBuilder {
         }
 
         *res.headers_mut() = self.headers;
-        Res {
-            inner: res,
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Res::from_hyper(res)
     }
 
     /// Build JSON response.
@@ -419,11 +477,7 @@ impl ResBuilder {
                 }
 
                 *res.headers_mut() = self.headers;
-                Res {
-                    inner: res,
-                    #[cfg(feature = "websocket")]
-                    ws_callback: None,
-                }
+                Res::from_hyper(res)
             }
             Err(_) => Res::builder().status(500).text("Failed to serialize JSON"),
         }
@@ -434,11 +488,7 @@ impl ResBuilder {
         let mut res = Response::new(Full::new(bytes.into()).map_err(|e| match e {}).boxed());
         *res.status_mut() = self.status;
         *res.headers_mut() = self.headers;
-        Res {
-            inner: res,
-            #[cfg(feature = "websocket")]
-            ws_callback: None,
-        }
+        Res::from_hyper(res)
     }
 }
 
@@ -447,3 +497,23 @@ impl Default for ResBuilder {
         Self::new()
     }
 }
+
+fn append_cookie(headers: &mut header::HeaderMap, cookie: Cookie<'static>) {
+    if let Ok(value) = header::HeaderValue::from_str(&cookie.encoded().to_string()) {
+        headers.append(header::SET_COOKIE, value);
+    }
+}
+
+/// Add `name` to `Vary` unless it is already listed or `Vary: *` is set.
+pub(crate) fn append_vary(headers: &mut header::HeaderMap, name: &'static str) {
+    let present = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case(name));
+
+    if !present {
+        headers.append(header::VARY, header::HeaderValue::from_static(name));
+    }
+}
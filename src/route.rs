@@ -1,22 +1,25 @@
 //! Per-route configuration with middleware support.
 
 use hyper::Method;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 
 use crate::{Handler, Middleware, handler::IntoHandler};
 
+/// Method key under which `any` routes are stored.
+pub(crate) static ANY: LazyLock<Method> = LazyLock::new(|| Method::from_bytes(b"*").unwrap());
+
 /// Route with per-route middleware.
 pub struct Route<S = ()> {
-    pub(crate) method: Method,
+    pub(crate) methods: Vec<Method>,
     pub(crate) path: String,
     pub(crate) handler: Arc<dyn Handler<S>>,
     pub(crate) middlewares: Arc<Vec<Arc<dyn Middleware<S>>>>,
 }
 
 impl<S: Send + Sync + 'static> Route<S> {
-    pub(crate) fn new(method: Method, path: String, handler: Arc<dyn Handler<S>>) -> Self {
+    pub(crate) fn new(methods: Vec<Method>, path: String, handler: Arc<dyn Handler<S>>) -> Self {
         Self {
-            method,
+            methods,
             path,
             handler,
             middlewares: Arc::new(Vec::new()),
@@ -37,7 +40,7 @@ impl<S: Send + Sync + 'static> Route<S> {
     where
         H: IntoHandler<S, T>,
     {
-        Self::new(Method::GET, path.into(), handler.into_handler())
+        Self::on(Method::GET, path, handler)
     }
 
     /// Create a POST route.
@@ -45,7 +48,7 @@ impl<S: Send + Sync + 'static> Route<S> {
     where
         H: IntoHandler<S, T>,
     {
-        Self::new(Method::POST, path.into(), handler.into_handler())
+        Self::on(Method::POST, path, handler)
     }
 
     /// Create a PUT route.
@@ -53,7 +56,7 @@ impl<S: Send + Sync + 'static> Route<S> {
     where
         H: IntoHandler<S, T>,
     {
-        Self::new(Method::PUT, path.into(), handler.into_handler())
+        Self::on(Method::PUT, path, handler)
     }
 
     /// Create a DELETE route.
@@ -61,7 +64,7 @@ impl<S: Send + Sync + 'static> Route<S> {
     where
         H: IntoHandler<S, T>,
     {
-        Self::new(Method::DELETE, path.into(), handler.into_handler())
+        Self::on(Method::DELETE, path, handler)
     }
 
     /// Create a PATCH route.
@@ -69,6 +72,67 @@ impl<S: Send + Sync + 'static> Route<S> {
     where
         H: IntoHandler<S, T>,
     {
-        Self::new(Method::PATCH, path.into(), handler.into_handler())
+        Self::on(Method::PATCH, path, handler)
+    }
+
+    /// Create a HEAD route.
+    pub fn head<H, T>(path: impl Into<String>, handler: H) -> Self
+    where
+        H: IntoHandler<S, T>,
+    {
+        Self::on(Method::HEAD, path, handler)
+    }
+
+    /// Create an OPTIONS route.
+    pub fn options<H, T>(path: impl Into<String>, handler: H) -> Self
+    where
+        H: IntoHandler<S, T>,
+    {
+        Self::on(Method::OPTIONS, path, handler)
+    }
+
+    /// Create a TRACE route.
+    pub fn trace<H, T>(path: impl Into<String>, handler: H) -> Self
+    where
+        H: IntoHandler<S, T>,
+    {
+        Self::on(Method::TRACE, path, handler)
+    }
+
+    /// Create a CONNECT route.
+    pub fn connect<H, T>(path: impl Into<String>, handler: H) -> Self
+    where
+        H: IntoHandler<S, T>,
+    {
+        Self::on(Method::CONNECT, path, handler)
+    }
+
+    /// Create a route matching any method without a more specific route.
+    pub fn any<H, T>(path: impl Into<String>, handler: H) -> Self
+    where
+        H: IntoHandler<S, T>,
+    {
+        Self::on(ANY.clone(), path, handler)
+    }
+
+    /// Create a route for `method`, including extension methods.
+    pub fn on<H, T>(method: Method, path: impl Into<String>, handler: H) -> Self
+    where
+        H: IntoHandler<S, T>,
+    {
+        Self::new(vec![method], path.into(), handler.into_handler())
+    }
+
+    /// Create a route sharing one handler across `methods`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `methods` is empty.
+    pub fn methods<H, T>(methods: &[Method], path: impl Into<String>, handler: H) -> Self
+    where
+        H: IntoHandler<S, T>,
+    {
+        assert!(!methods.is_empty(), "route methods must not be empty");
+        Self::new(methods.to_vec(), path.into(), handler.into_handler())
     }
 }
@@ -3,17 +3,21 @@
 use hyper::Method;
 use std::sync::Arc;
 
+use crate::route::ANY;
 use crate::{Handler, Middleware, handler::IntoHandler};
 
 type BoxedHandler<S> = Arc<dyn Handler<S>>;
 type BoxedMiddleware<S> = Arc<dyn Middleware<S>>;
 type SharedMiddlewares<S> = Arc<Vec<BoxedMiddleware<S>>>;
+pub(crate) type FlatRoute<S> = (Method, String, BoxedHandler<S>, SharedMiddlewares<S>);
+pub(crate) type FlatFallback<S> = (String, BoxedHandler<S>, SharedMiddlewares<S>);
 
 /// Router for grouping routes with shared middleware.
 pub struct Router<S = ()> {
     routes: Vec<(Method, String, BoxedHandler<S>)>,
     middlewares: Vec<BoxedMiddleware<S>>,
     nested: Vec<(String, Router<S>)>,
+    fallback: Option<BoxedHandler<S>>,
 }
 
 impl<S: Send + Sync + 'static> Router<S> {
@@ -23,6 +27,7 @@ impl<S: Send + Sync + 'static> Router<S> {
             routes: Vec::with_capacity(routes),
             middlewares: Vec::with_capacity(middlewares),
             nested: Vec::new(),
+            fallback: None,
         }
     }
 
@@ -36,8 +41,7 @@ impl<S: Send + Sync + 'static> Router<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes
-            .push((Method::GET, path.to_string(), handler.into_handler()));
+        self.on(Method::GET, path, handler);
     }
 
     /// Register a POST route.
@@ -45,8 +49,7 @@ impl<S: Send + Sync + 'static> Router<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes
-            .push((Method::POST, path.to_string(), handler.into_handler()));
+        self.on(Method::POST, path, handler);
     }
 
     /// Register a PUT route.
@@ -54,8 +57,7 @@ impl<S: Send + Sync + 'static> Router<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes
-            .push((Method::PUT, path.to_string(), handler.into_handler()));
+        self.on(Method::PUT, path, handler);
     }
 
     /// Register a DELETE route.
@@ -63,17 +65,93 @@ impl<S: Send + Sync + 'static> Router<S> {
     where
         H: IntoHandler<S, T>,
     {
-        self.routes
-            .push((Method::DELETE, path.to_string(), handler.into_handler()));
+        self.on(Method::DELETE, path, handler);
     }
 
     /// Register a PATCH route.
     pub fn patch<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::PATCH, path, handler);
+    }
+
+    /// Register a HEAD route.
+    pub fn head<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::HEAD, path, handler);
+    }
+
+    /// Register an OPTIONS route.
+    pub fn options<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::OPTIONS, path, handler);
+    }
+
+    /// Register a TRACE route.
+    pub fn trace<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::TRACE, path, handler);
+    }
+
+    /// Register a CONNECT route.
+    pub fn connect<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(Method::CONNECT, path, handler);
+    }
+
+    /// Register a route matching any method without a more specific route.
+    pub fn any<H, T>(&mut self, path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.on(ANY.clone(), path, handler);
+    }
+
+    /// Register a route for `method`, including extension methods.
+    pub fn on<H, T>(&mut self, method: Method, path: &str, handler: H)
     where
         H: IntoHandler<S, T>,
     {
         self.routes
-            .push((Method::PATCH, path.to_string(), handler.into_handler()));
+            .push((method, path.to_string(), handler.into_handler()));
+    }
+
+    /// Register one handler for each of `methods`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `methods` is empty.
+    pub fn methods<H, T>(&mut self, methods: &[Method], path: &str, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        assert!(!methods.is_empty(), "route methods must not be empty");
+        let handler = handler.into_handler();
+        for method in methods {
+            self.routes
+                .push((method.clone(), path.to_string(), Arc::clone(&handler)));
+        }
+    }
+
+    /// Handle requests under this router's prefix that match no route.
+    ///
+    /// The router's middleware runs for fallback responses too. The innermost
+    /// router with a fallback wins; paths outside every prefix use
+    /// [`Foton::fallback`](crate::Foton::fallback).
+    pub fn fallback<H, T>(&mut self, handler: H)
+    where
+        H: IntoHandler<S, T>,
+    {
+        self.fallback = Some(handler.into_handler());
     }
 
     /// Attach middleware to this router.
@@ -95,19 +173,18 @@ impl<S: Send + Sync + 'static> Router<S> {
         self.routes.len()
     }
 
-    pub(crate) fn flatten(
-        self,
-        prefix: &str,
-    ) -> Vec<(Method, String, BoxedHandler<S>, SharedMiddlewares<S>)> {
-        self.flatten_with_shared("", prefix, None)
+    pub(crate) fn flatten(self, prefix: &str) -> (Vec<FlatRoute<S>>, Vec<FlatFallback<S>>) {
+        let mut fallbacks = Vec::new();
+        let routes = self.flatten_with_shared(prefix, None, &mut fallbacks);
+        (routes, fallbacks)
     }
 
     fn flatten_with_shared(
         self,
-        base_prefix: &str,
         prefix: &str,
         parent_middlewares: Option<&SharedMiddlewares<S>>,
-    ) -> Vec<(Method, String, BoxedHandler<S>, SharedMiddlewares<S>)> {
+        fallbacks: &mut Vec<FlatFallback<S>>,
+    ) -> Vec<FlatRoute<S>> {
         let estimated_size = self.routes.len()
             + self
                 .nested
@@ -129,6 +206,14 @@ impl<S: Send + Sync + 'static> Router<S> {
             Arc::new(self.middlewares.clone())
         };
 
+        if let Some(fallback) = self.fallback {
+            fallbacks.push((
+                prefix.to_string(),
+                fallback,
+                Arc::clone(&combined_middlewares),
+            ));
+        }
+
         for (method, path, handler) in self.routes {
             let full_path = if prefix.is_empty() {
                 path.clone()
@@ -152,9 +237,9 @@ impl<S: Send + Sync + 'static> Router<S> {
             };
 
             let nested_routes = nested_router.flatten_with_shared(
-                base_prefix,
                 &full_prefix,
                 Some(&combined_middlewares),
+                fallbacks,
             );
             flattened.extend(nested_routes);
         }
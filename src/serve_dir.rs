@@ -0,0 +1,260 @@
+//! Static directory serving.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::{Foton, ServeDir};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!
+//!     // Serve ./public under /static/
+//!     app.nest("/static", ServeDir::new("public").into());
+//!
+//!     // Or map a wildcard route onto a directory, falling back to the SPA entry
+//!     app.get("/app/{*path}", ServeDir::new("dist").spa_fallback("index.html"));
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_trait::async_trait;
+use percent_encoding::percent_decode_str;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{Error, Handler, IntoRes, Req, Res, Router};
+
+/// Handler serving files from a directory.
+///
+/// The file is taken from the `path` route parameter (e.g. `/assets/{*path}`)
+/// and resolved under the root. Requests escaping the root through `..`
+/// segments or symlinks get 404.
+#[derive(Debug, Clone)]
+pub struct ServeDir {
+    root: PathBuf,
+    index_file: Option<String>,
+    spa_fallback: Option<PathBuf>,
+}
+
+impl ServeDir {
+    /// Serve files under `root`, with `index.html` as directory index.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            index_file: Some("index.html".to_string()),
+            spa_fallback: None,
+        }
+    }
+
+    /// Set the file served for directory requests.
+    pub fn index_file(mut self, name: impl Into<String>) -> Self {
+        self.index_file = Some(name.into());
+        self
+    }
+
+    /// Respond 404 to directory requests instead of serving an index file.
+    pub fn no_index(mut self) -> Self {
+        self.index_file = None;
+        self
+    }
+
+    /// Serve this file (relative to the root) for paths that don't exist.
+    ///
+    /// Lets single-page applications handle client-side routes.
+    pub fn spa_fallback(mut self, file: impl Into<PathBuf>) -> Self {
+        self.spa_fallback = Some(file.into());
+        self
+    }
+
+    async fn serve(&self, req: &Req) -> Res {
+        let Some(relative) = req.param("path").map_or(Some(PathBuf::new()), sanitize) else {
+            return Error::not_found("File not found").into_res();
+        };
+
+        let root = match tokio::fs::canonicalize(&self.root).await {
+            Ok(root) => root,
+            Err(_) => return Error::not_found("File not found").into_res(),
+        };
+
+        match resolve(&root, &relative).await {
+            Some((path, true)) => {
+                // Redirect so relative links in the index resolve inside the directory
+                if !req.path().ends_with('/') {
+                    let location = match req.query() {
+                        Some(query) => format!("{}/?{}", req.path(), query),
+                        None => format!("{}/", req.path()),
+                    };
+                    return Res::status(308).header("location", location);
+                }
+
+                let index = self.index_file.as_ref().map(|index| path.join(index));
+                match index {
+                    Some(index) => match resolve(&root, &index).await {
+                        Some((index, false)) => Res::file(index).await,
+                        _ => self.fallback(&root).await,
+                    },
+                    None => self.fallback(&root).await,
+                }
+            }
+            Some((path, false)) => Res::file(path).await,
+            None => self.fallback(&root).await,
+        }
+    }
+
+    async fn fallback(&self, root: &Path) -> Res {
+        if let Some(fallback) = &self.spa_fallback {
+            if let Some((path, false)) = resolve(root, &root.join(fallback)).await {
+                return Res::file(path).await;
+            }
+        }
+        Error::not_found("File not found").into_res()
+    }
+}
+
+#[async_trait]
+impl<S> Handler<S> for ServeDir
+where
+    S: Send + Sync + 'static,
+{
+    async fn call(&self, req: Req, _state: Arc<S>) -> Res {
+        self.serve(&req).await
+    }
+}
+
+impl<S> From<ServeDir> for Router<S>
+where
+    S: Send + Sync + 'static,
+{
+    /// Router serving the directory at `/` and `/{*path}`, for [`Foton::nest`](crate::Foton::nest).
+    fn from(dir: ServeDir) -> Self {
+        let mut router = Router::new();
+        router.get("/", dir.clone());
+        router.get("/{*path}", dir);
+        router
+    }
+}
+
+/// Percent-decode a request path and reduce it to normal components.
+///
+/// Returns `None` for `..` segments and for characters that could change the
+/// meaning of the path on some platforms.
+fn sanitize(path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(path).decode_utf8().ok()?;
+    let mut relative = PathBuf::new();
+
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            s if s.contains(['\\', '\0', ':']) => return None,
+            s => relative.push(s),
+        }
+    }
+
+    Some(relative)
+}
+
+/// Resolve `path` under `root`, following symlinks.
+///
+/// Returns the canonical path and whether it is a directory, or `None` if it
+/// doesn't exist or resolves outside the root.
+async fn resolve(root: &Path, path: &Path) -> Option<(PathBuf, bool)> {
+    let path = tokio::fs::canonicalize(root.join(path)).await.ok()?;
+    if !path.starts_with(root) {
+        return None;
+    }
+    let metadata = tokio::fs::metadata(&path).await.ok()?;
+    Some((path, metadata.is_dir()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Foton;
+    use crate::test::TempPath;
+
+    fn fixture() -> TempPath {
+        let dir = TempPath::new("serve-dir");
+        std::fs::create_dir_all(dir.join("public/docs")).unwrap();
+        std::fs::write(dir.join("public/index.html"), "<h1>home</h1>").unwrap();
+        std::fs::write(dir.join("public/app.js"), "run()").unwrap();
+        std::fs::write(dir.join("public/docs/index.html"), "docs").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("public/escape.txt")).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_serve_dir_nested() {
+        let dir = fixture();
+        let mut app = Foton::new();
+        app.nest("/static", ServeDir::new(dir.join("public")).into());
+        let client = app.into_test_client();
+
+        let res = client.get("/static/app.js").send().await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(
+            res.headers()["content-type"],
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(res.into_text().await.unwrap(), "run()");
+
+        let res = client.get("/static/").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "<h1>home</h1>");
+
+        let res = client.get("/static/docs?x=1").send().await;
+        assert_eq!(res.status_code(), 308);
+        assert_eq!(res.headers()["location"], "/static/docs/?x=1");
+
+        let res = client.get("/static/docs/").send().await;
+        assert_eq!(res.into_text().await.unwrap(), "docs");
+
+        let res = client.get("/static/missing.css").send().await;
+        assert_eq!(res.status_code(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_serve_dir_rejects_escapes() {
+        let dir = fixture();
+        let mut app = Foton::new();
+        app.get("/files/{*path}", ServeDir::new(dir.join("public")));
+        let client = app.into_test_client();
+
+        for path in [
+            "/files/../secret.txt",
+            "/files/%2e%2e/secret.txt",
+            "/files/docs/%2E%2E%2F..%2Fsecret.txt",
+            "/files/..%5csecret.txt",
+        ] {
+            let res = client.get(path).send().await;
+            assert_eq!(res.status_code(), 404, "{}", path);
+        }
+
+        #[cfg(unix)]
+        {
+            let res = client.get("/files/escape.txt").send().await;
+            assert_eq!(res.status_code(), 404);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve_dir_spa_fallback() {
+        let dir = fixture();
+        let mut app = Foton::new();
+        app.get(
+            "/app/{*path}",
+            ServeDir::new(dir.join("public")).spa_fallback("index.html"),
+        );
+        let client = app.into_test_client();
+
+        let res = client.get("/app/users/42").send().await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.into_text().await.unwrap(), "<h1>home</h1>");
+
+        let res = client.get("/app/%2e%2e/secret.txt").send().await;
+        assert_eq!(res.status_code(), 404);
+    }
+}
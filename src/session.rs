@@ -0,0 +1,748 @@
+//! Server-side sessions keyed by a cookie.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::session::MemoryStore;
+//! use foton::{Foton, Res, Session, Sessions};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut app = Foton::new();
+//!     app.attach(Sessions::new(MemoryStore::new()).ttl(Duration::from_secs(3600)));
+//!
+//!     app.post("/login", |session: Session| async move {
+//!         // New privileges, new ID: defeats session fixation
+//!         session.rotate_id();
+//!         session.insert("user_id", 42)?;
+//!         Ok::<_, foton::Error>(Res::text("logged in"))
+//!     });
+//!
+//!     app.get("/me", |session: Session| async move {
+//!         match session.get::<u64>("user_id") {
+//!             Some(id) => Res::text(format!("user {}", id)),
+//!             None => Res::status(401),
+//!         }
+//!     });
+//!
+//!     app.listen(([127, 0, 0, 1], 3000)).await.unwrap();
+//! }
+//! ```
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::cookies::{Cookie, Cookies, SameSite, time};
+use crate::{Error, FromRequest, Middleware, Next, Req, Res, Result};
+
+/// Session contents: string keys to JSON values.
+pub type SessionData = HashMap<String, Value>;
+
+/// Backend persisting session data by ID.
+#[async_trait]
+pub trait SessionStore: Send + Sync + 'static {
+    /// Load a session, or `None` if it does not exist or has expired.
+    async fn load(&self, id: &str) -> Result<Option<SessionData>>;
+
+    /// Create or replace a session, expiring after `ttl`.
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<()>;
+
+    /// Delete a session. Missing sessions are not an error.
+    async fn delete(&self, id: &str) -> Result<()>;
+}
+
+/// In-memory session store with TTL eviction.
+///
+/// Expired sessions are ignored on load and swept out periodically on save.
+/// Clones share the same storage.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    inner: Arc<Mutex<MemoryInner>>,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    sessions: HashMap<String, (SessionData, Instant)>,
+    last_sweep: Option<Instant>,
+}
+
+/// How often the memory store sweeps expired sessions.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored sessions, including expired ones not yet swept.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().sessions.len()
+    }
+
+    /// Whether the store holds no sessions.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.sessions.get(id) {
+            Some((data, expires)) if *expires > Instant::now() => Ok(Some(data.clone())),
+            Some(_) => {
+                inner.sessions.remove(id);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<()> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .last_sweep
+            .is_none_or(|last| now.duration_since(last) >= SWEEP_INTERVAL)
+        {
+            inner.sessions.retain(|_, (_, expires)| *expires > now);
+            inner.last_sweep = Some(now);
+        }
+        inner
+            .sessions
+            .insert(id.to_string(), (data.clone(), now + ttl));
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        self.inner.lock().unwrap().sessions.remove(id);
+        Ok(())
+    }
+}
+
+/// Session store writing one JSON file per session into a directory.
+///
+/// Expired files are removed when loaded or by [`FileStore::remove_expired`].
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, serde::Deserialize)]
+struct StoredSession {
+    expires: u64,
+    data: SessionData,
+}
+
+impl FileStore {
+    /// Store sessions in `dir`, which is created on first save.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// File for `id`, or `None` if `id` is not one this store could have
+    /// issued, so it can never point outside the directory.
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        is_valid_id(id).then(|| self.dir.join(format!("{}.json", id)))
+    }
+
+    /// Delete every expired session file.
+    pub async fn remove_expired(&self) -> Result<()> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let now = unix_now();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            // Only touch files this store wrote
+            let is_session = path.extension().is_some_and(|ext| ext == "json")
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(is_valid_id);
+            if !is_session {
+                continue;
+            }
+            let expired = match tokio::fs::read(&path).await {
+                Ok(bytes) => serde_json::from_slice::<StoredSession>(&bytes)
+                    .map_or(true, |stored| stored.expires <= now),
+                Err(_) => continue,
+            };
+            if expired {
+                let _ = tokio::fs::remove_file(&path).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionData>> {
+        let Some(path) = self.path(id) else {
+            return Ok(None);
+        };
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_slice::<StoredSession>(&bytes) {
+            Ok(stored) if stored.expires > unix_now() => Ok(Some(stored.data)),
+            _ => {
+                let _ = tokio::fs::remove_file(&path).await;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<()> {
+        let Some(path) = self.path(id) else {
+            return Err(Error::internal("invalid session ID"));
+        };
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let stored = StoredSession {
+            expires: unix_now() + ttl.as_secs(),
+            data: data.clone(),
+        };
+        let bytes = serde_json::to_vec(&stored).map_err(|e| Error::Json(e.to_string()))?;
+
+        // Write then rename, so readers never see a partial file. Each save
+        // gets its own temporary file, so concurrent saves cannot interleave.
+        let tmp = self
+            .dir
+            .join(format!("{}.{}.tmp", id, uuid::Uuid::new_v4().simple()));
+        let written = match tokio::fs::write(&tmp, bytes).await {
+            Ok(()) => tokio::fs::rename(&tmp, path).await,
+            Err(e) => Err(e),
+        };
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&tmp).await;
+        }
+        Ok(written?)
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        let Some(path) = self.path(id) else {
+            return Ok(());
+        };
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Session attached to the current request by [`Sessions`].
+///
+/// Changes are saved after the handler returns, and only if the session was
+/// modified. Clones share the same session.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<Mutex<SessionState>>,
+}
+
+struct SessionState {
+    id: Option<String>,
+    data: SessionData,
+    modified: bool,
+    rotate: bool,
+    destroyed: bool,
+}
+
+impl Session {
+    fn new(id: Option<String>, data: SessionData) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SessionState {
+                id,
+                data,
+                modified: false,
+                rotate: false,
+                destroyed: false,
+            })),
+        }
+    }
+
+    /// Current session ID, or `None` until a new session is first saved.
+    pub fn id(&self) -> Option<String> {
+        self.inner.lock().unwrap().id.clone()
+    }
+
+    /// Get a value, or `None` if missing or of a different type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        let value = inner.data.get(key)?;
+        T::deserialize(value).ok()
+    }
+
+    /// Set a value.
+    pub fn insert<T: Serialize>(&self, key: impl Into<String>, value: T) -> Result<()> {
+        let value = serde_json::to_value(value).map_err(|e| Error::Json(e.to_string()))?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.insert(key.into(), value);
+        inner.modified = true;
+        Ok(())
+    }
+
+    /// Remove a value, returning whether it was present.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let removed = inner.data.remove(key).is_some();
+        inner.modified |= removed;
+        removed
+    }
+
+    /// Remove every value.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.data.is_empty() {
+            inner.data.clear();
+            inner.modified = true;
+        }
+    }
+
+    /// Copy of all values.
+    pub fn data(&self) -> SessionData {
+        self.inner.lock().unwrap().data.clone()
+    }
+
+    /// All values as one struct, or `None` if they do not deserialize into `T`.
+    pub fn data_as<T: DeserializeOwned>(&self) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        let map = inner
+            .data
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        T::deserialize(Value::Object(map)).ok()
+    }
+
+    /// Replace all values with the fields of `value`, which must serialize
+    /// to a JSON object.
+    pub fn set_data<T: Serialize>(&self, value: T) -> Result<()> {
+        let Value::Object(map) =
+            serde_json::to_value(value).map_err(|e| Error::Json(e.to_string()))?
+        else {
+            return Err(Error::Json("session data must be an object".to_string()));
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.data = map.into_iter().collect();
+        inner.modified = true;
+        Ok(())
+    }
+
+    /// Issue a new ID when the session is saved, keeping the data.
+    ///
+    /// Call this on login and other privilege changes.
+    pub fn rotate_id(&self) {
+        self.inner.lock().unwrap().rotate = true;
+    }
+
+    /// Delete the session from the store and expire its cookie.
+    pub fn destroy(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.clear();
+        inner.destroyed = true;
+    }
+
+    /// Whether the session has unsaved changes.
+    pub fn is_modified(&self) -> bool {
+        self.inner.lock().unwrap().modified
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for Session
+where
+    S: Send + Sync + 'static,
+{
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        req.extensions()
+            .get::<Session>()
+            .cloned()
+            .ok_or_else(|| Error::internal("Session used without the Sessions middleware"))
+    }
+}
+
+/// Middleware loading a [`Session`] from a cookie and saving it afterwards.
+///
+/// The cookie holds only a random ID. IDs the store does not know are never
+/// reused, so clients cannot pick their own session ID.
+pub struct Sessions<T> {
+    store: T,
+    cookie_name: String,
+    ttl: Duration,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+    same_site: SameSite,
+}
+
+impl<T: SessionStore> Sessions<T> {
+    /// Use `store`, with a 24 hour TTL and an `HttpOnly`, `SameSite=Lax`,
+    /// `Secure` cookie named `id`.
+    pub fn new(store: T) -> Self {
+        Self {
+            store,
+            cookie_name: "id".to_string(),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            path: "/".to_string(),
+            domain: None,
+            secure: true,
+            same_site: SameSite::Lax,
+        }
+    }
+
+    /// Set the cookie name.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Expire sessions this long after their last change.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the cookie path.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Set the cookie domain.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Set the `Secure` attribute. Disable only for plain-HTTP development.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    fn cookie(&self, value: String) -> Cookie<'static> {
+        let mut cookie = Cookie::build((self.cookie_name.clone(), value))
+            .path(self.path.clone())
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .max_age(time::Duration::seconds(self.ttl.as_secs() as i64))
+            .build();
+        if let Some(domain) = &self.domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+
+    async fn load(&self, req: &Req) -> Result<Session> {
+        let id = Cookies::from_req(req)
+            .get(&self.cookie_name)
+            .map(|c| c.value().to_string())
+            .filter(|id| is_valid_id(id));
+
+        if let Some(id) = id {
+            if let Some(data) = self.store.load(&id).await? {
+                return Ok(Session::new(Some(id), data));
+            }
+        }
+        Ok(Session::new(None, SessionData::new()))
+    }
+
+    async fn persist(&self, session: &Session, res: Res) -> Result<Res> {
+        let (id, data, modified, rotate, destroyed) = {
+            let inner = session.inner.lock().unwrap();
+            (
+                inner.id.clone(),
+                inner.data.clone(),
+                inner.modified,
+                inner.rotate,
+                inner.destroyed,
+            )
+        };
+
+        if destroyed {
+            let Some(id) = id else {
+                return Ok(res);
+            };
+            self.store.delete(&id).await?;
+            return Ok(res.remove_cookie(self.cookie(String::new())));
+        }
+
+        if !modified && !rotate {
+            return Ok(res);
+        }
+        if id.is_none() && data.is_empty() {
+            return Ok(res);
+        }
+
+        let new_id = match id {
+            Some(id) if !rotate => id,
+            old => {
+                if let Some(old) = old {
+                    self.store.delete(&old).await?;
+                }
+                generate_id()
+            }
+        };
+        self.store.save(&new_id, &data, self.ttl).await?;
+
+        // The cookie's Max-Age tracks the store TTL, so it is refreshed on every save
+        Ok(res.cookie(self.cookie(new_id)))
+    }
+}
+
+#[async_trait]
+impl<T, S> Middleware<S> for Sessions<T>
+where
+    T: SessionStore,
+    S: Send + Sync + 'static,
+{
+    async fn handle(&self, mut req: Req, _state: Arc<S>, next: Next<S>) -> Res {
+        let session = match self.load(&req).await {
+            Ok(session) => session,
+            Err(e) => return crate::IntoRes::into_res(e),
+        };
+        req.extensions_mut().insert(session.clone());
+
+        let res = next.run(req).await;
+
+        match self.persist(&session, res).await {
+            Ok(res) => res,
+            Err(e) => crate::IntoRes::into_res(e),
+        }
+    }
+}
+
+/// 256 random bits, hex encoded.
+fn generate_id() -> String {
+    let a = uuid::Uuid::new_v4();
+    let b = uuid::Uuid::new_v4();
+    format!("{}{}", a.simple(), b.simple())
+}
+
+/// IDs are only ever hex, which also keeps them safe as file names.
+fn is_valid_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Foton;
+    use crate::test::TempPath;
+
+    fn app<T: SessionStore>(store: T) -> crate::test::TestClient {
+        let mut app = Foton::new();
+        app.attach(Sessions::new(store));
+        app.post("/login", |session: Session| async move {
+            session.rotate_id();
+            session.insert("user", "alice")?;
+            Ok::<_, Error>("ok")
+        });
+        app.get("/me", |session: Session| async move {
+            session.get::<String>("user").unwrap_or_default()
+        });
+        app.post("/logout", |session: Session| async move {
+            session.destroy();
+            "bye"
+        });
+        app.into_test_client()
+    }
+
+    fn session_cookie(res: &Res) -> Option<String> {
+        let header = res.headers().get("set-cookie")?.to_str().ok()?;
+        Some(header.split(';').next()?.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let store = MemoryStore::new();
+        let client = app(store.clone());
+
+        // Reading without a session saves nothing
+        let res = client.get("/me").send().await;
+        assert!(res.headers().get("set-cookie").is_none());
+        assert!(store.is_empty());
+
+        let res = client.post("/login").send().await;
+        let first = session_cookie(&res).unwrap();
+        assert!(first.starts_with("id="));
+        assert_eq!(store.len(), 1);
+
+        let res = client.get("/me").header("cookie", &first).send().await;
+        assert!(res.headers().get("set-cookie").is_none());
+        assert_eq!(res.into_text().await.unwrap(), "alice");
+
+        // Logging in again rotates the ID and drops the old one
+        let res = client.post("/login").header("cookie", &first).send().await;
+        let second = session_cookie(&res).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(store.len(), 1);
+        let res = client.get("/me").header("cookie", &first).send().await;
+        assert_eq!(res.into_text().await.unwrap(), "");
+
+        let res = client
+            .post("/logout")
+            .header("cookie", &second)
+            .send()
+            .await;
+        assert!(
+            res.headers()["set-cookie"]
+                .to_str()
+                .unwrap()
+                .contains("Max-Age=0")
+        );
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_ids_are_not_adopted() {
+        let store = MemoryStore::new();
+        let client = app(store.clone());
+
+        let chosen = format!("id={}", "a".repeat(64));
+        let res = client.post("/login").header("cookie", &chosen).send().await;
+        assert_ne!(session_cookie(&res).unwrap(), chosen);
+    }
+
+    #[tokio::test]
+    async fn test_store_expiry() {
+        let store = MemoryStore::new();
+        let mut data = SessionData::new();
+        data.insert("k".to_string(), Value::from(1));
+        store
+            .save("short", &data, Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert!(store.load("short").await.unwrap().is_some());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(store.load("short").await.unwrap().is_none());
+
+        let dir = TempPath::new("sessions");
+        let files = FileStore::new(dir.to_path_buf());
+        let (live, dead) = (generate_id(), generate_id());
+        files
+            .save(&live, &data, Duration::from_secs(60))
+            .await
+            .unwrap();
+        files.save(&dead, &data, Duration::ZERO).await.unwrap();
+        assert_eq!(files.load(&live).await.unwrap(), Some(data.clone()));
+
+        // Saves overlapping on one ID never share a temporary file
+        let saves = (0..8).map(|_| files.save(&live, &data, Duration::from_secs(60)));
+        for result in futures_util::future::join_all(saves).await {
+            result.unwrap();
+        }
+
+        // Files the store did not write are left alone, even if unreadable
+        tokio::fs::write(dir.join("notes.json"), "not a session")
+            .await
+            .unwrap();
+        files.remove_expired().await.unwrap();
+        assert!(!dir.join(format!("{}.json", dead)).exists());
+        assert!(dir.join("notes.json").exists());
+        let mut names = Vec::new();
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().into_string().unwrap());
+        }
+        names.sort();
+        assert_eq!(names, [format!("{}.json", live), "notes.json".to_string()]);
+
+        files.delete(&live).await.unwrap();
+        assert!(files.load(&live).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_store_rejects_invalid_ids() {
+        let dir = TempPath::new("sessions");
+        let files = FileStore::new(dir.join("store"));
+        tokio::fs::create_dir_all(dir.join("store")).await.unwrap();
+        tokio::fs::write(dir.join("x.json"), "{}").await.unwrap();
+
+        let mut data = SessionData::new();
+        data.insert("k".to_string(), Value::from(1));
+        for id in ["../x", "short", ""] {
+            assert!(
+                files
+                    .save(id, &data, Duration::from_secs(60))
+                    .await
+                    .is_err()
+            );
+            assert!(files.load(id).await.unwrap().is_none());
+            files.delete(id).await.unwrap();
+        }
+        // Nothing was written, read or removed outside the store
+        assert_eq!(std::fs::read_to_string(dir.join("x.json")).unwrap(), "{}");
+        assert_eq!(std::fs::read_dir(&*dir).unwrap().count(), 2);
+        assert_eq!(std::fs::read_dir(dir.join("store")).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_typed_session_data() {
+        #[derive(Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Profile {
+            user: String,
+            visits: u32,
+        }
+
+        let mut app = Foton::new();
+        app.attach(Sessions::new(MemoryStore::new()));
+        app.post("/", |session: Session| async move {
+            let mut profile = session.data_as::<Profile>().unwrap_or(Profile {
+                user: "alice".to_string(),
+                visits: 0,
+            });
+            profile.visits += 1;
+            session.set_data(&profile)?;
+            Ok::<_, Error>(profile.visits.to_string())
+        });
+        let client = app.into_test_client();
+
+        let res = client.post("/").send().await;
+        let cookie = session_cookie(&res).unwrap();
+        assert_eq!(res.into_text().await.unwrap(), "1");
+        let res = client.post("/").header("cookie", &cookie).send().await;
+        assert_eq!(res.into_text().await.unwrap(), "2");
+    }
+
+    #[test]
+    fn test_set_data_requires_an_object() {
+        let session = Session::new(None, SessionData::new());
+        assert!(session.set_data(7).is_err());
+        assert!(session.set_data(["a", "b"]).is_err());
+        assert!(session.data_as::<SessionData>().unwrap().is_empty());
+
+        session
+            .set_data(serde_json::json!({ "user": "alice" }))
+            .unwrap();
+        assert_eq!(session.get::<String>("user").as_deref(), Some("alice"));
+    }
+}
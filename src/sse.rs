@@ -0,0 +1,324 @@
+//! Server-Sent Events.
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use foton::{Event, IntoRes, LastEventId, Res, Sse, SseSender};
+//! use std::time::Duration;
+//!
+//! async fn events(LastEventId(last): LastEventId) -> Res {
+//!     // Resume after the last event the client saw
+//!     let start = last.and_then(|id| id.parse::<u64>().ok()).map_or(0, |id| id + 1);
+//!
+//!     Sse::new(move |tx: SseSender| async move {
+//!         for i in start.. {
+//!             let event = Event::default().id(i.to_string()).data(format!("tick {}", i));
+//!             if tx.send(event).await.is_err() {
+//!                 break;
+//!             }
+//!             tokio::time::sleep(Duration::from_secs(1)).await;
+//!         }
+//!     })
+//!     .keep_alive(Duration::from_secs(15))
+//!     .into_res()
+//! }
+//! ```
+
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::extractors::FromRequest;
+use crate::{Error, IntoRes, Req, Res, Result, StreamSender};
+
+/// A single Server-Sent Event.
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comments: Vec<String>,
+}
+
+impl Event {
+    /// Set the event data. Multi-line data is split into several `data:` lines.
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Set the event data to a value serialized as JSON.
+    pub fn json_data<T: Serialize>(self, value: &T) -> Result<Self> {
+        let data = serde_json::to_string(value).map_err(|e| Error::Json(e.to_string()))?;
+        Ok(self.data(data))
+    }
+
+    /// Set the event type. Line breaks are removed.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(strip_newlines(event.into()));
+        self
+    }
+
+    /// Set the event ID. Line breaks are removed.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(strip_newlines(id.into()));
+        self
+    }
+
+    /// Set the client reconnection delay.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Add a comment line, ignored by clients.
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comments.push(comment.into());
+        self
+    }
+
+    /// Encode to the `text/event-stream` wire format.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+
+        for comment in &self.comments {
+            for line in split_lines(comment) {
+                write_field(&mut buf, "", line);
+            }
+        }
+        if let Some(event) = &self.event {
+            write_field(&mut buf, "event", event);
+        }
+        if let Some(id) = &self.id {
+            write_field(&mut buf, "id", id);
+        }
+        if let Some(retry) = self.retry {
+            write_field(&mut buf, "retry", &retry.as_millis().to_string());
+        }
+        if let Some(data) = &self.data {
+            for line in split_lines(data) {
+                write_field(&mut buf, "data", line);
+            }
+        }
+
+        buf.put_u8(b'\n');
+        buf.freeze()
+    }
+}
+
+fn write_field(buf: &mut BytesMut, name: &str, value: &str) {
+    buf.put_slice(name.as_bytes());
+    buf.put_u8(b':');
+    if !value.is_empty() {
+        buf.put_u8(b' ');
+        buf.put_slice(value.as_bytes());
+    }
+    buf.put_u8(b'\n');
+}
+
+/// Split on CRLF, CR or LF, keeping a single empty line for empty input.
+fn split_lines(value: &str) -> impl Iterator<Item = &str> {
+    value.split("\r\n").flat_map(|l| l.split(['\r', '\n']))
+}
+
+fn strip_newlines(value: String) -> String {
+    if value.contains(['\r', '\n']) {
+        value.replace(['\r', '\n'], "")
+    } else {
+        value
+    }
+}
+
+/// Channel sender for Server-Sent Events.
+#[derive(Clone)]
+pub struct SseSender {
+    tx: mpsc::Sender<Event>,
+}
+
+impl SseSender {
+    /// Send an event. Fails once the client has disconnected.
+    pub async fn send(&self, event: Event) -> Result<()> {
+        self.tx
+            .send(event)
+            .await
+            .map_err(|_| Error::Custom("Stream channel closed".into()))
+    }
+
+    /// Send an event with only data.
+    pub async fn send_data(&self, data: impl Into<String>) -> Result<()> {
+        self.send(Event::default().data(data)).await
+    }
+}
+
+/// Server-Sent Events response.
+///
+/// The handler receives an [`SseSender`]; the stream ends when every sender is dropped.
+pub struct Sse<F> {
+    handler: F,
+    keep_alive: Option<Duration>,
+    keep_alive_text: String,
+}
+
+impl<F, Fut> Sse<F>
+where
+    F: FnOnce(SseSender) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    /// Create an event stream driven by `handler`.
+    pub fn new(handler: F) -> Self {
+        Self {
+            handler,
+            keep_alive: None,
+            keep_alive_text: String::new(),
+        }
+    }
+
+    /// Send a comment every `interval` while no events are sent.
+    ///
+    /// Keeps proxies and load balancers from closing idle connections.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Set the text of keep-alive comments (empty by default).
+    pub fn keep_alive_text(mut self, text: impl Into<String>) -> Self {
+        self.keep_alive_text = text.into();
+        self
+    }
+}
+
+impl<F, Fut> IntoRes for Sse<F>
+where
+    F: FnOnce(SseSender) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn into_res(self) -> Res {
+        let (tx, rx) = mpsc::channel::<Event>(32);
+        let handler = self.handler;
+        let keep_alive = self.keep_alive;
+        let keep_alive_event = Event::default().comment(self.keep_alive_text).to_bytes();
+
+        tokio::spawn(handler(SseSender { tx }));
+
+        Res::stream(move |stream: StreamSender| {
+            forward_events(rx, stream, keep_alive, keep_alive_event)
+        })
+        .header("content-type", "text/event-stream")
+        .header("cache-control", "no-cache")
+    }
+}
+
+async fn forward_events(
+    mut rx: mpsc::Receiver<Event>,
+    mut stream: StreamSender,
+    keep_alive: Option<Duration>,
+    keep_alive_event: Bytes,
+) {
+    loop {
+        let next = match keep_alive {
+            Some(interval) => match tokio::time::timeout(interval, rx.recv()).await {
+                Ok(next) => next.map(|event| event.to_bytes()),
+                Err(_) => Some(keep_alive_event.clone()),
+            },
+            None => rx.recv().await.map(|event| event.to_bytes()),
+        };
+
+        let Some(chunk) = next else {
+            break;
+        };
+        if stream.send(chunk).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// `Last-Event-ID` header sent by reconnecting EventSource clients.
+pub struct LastEventId(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequest<S> for LastEventId
+where
+    S: Send + Sync + 'static,
+{
+    #[inline]
+    async fn from_request(req: &mut Req, _state: &Arc<S>) -> Result<Self> {
+        Ok(LastEventId(req.header("last-event-id").map(str::to_string)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Foton;
+
+    #[test]
+    fn test_event_format() {
+        let event = Event::default()
+            .comment("hello")
+            .event("update")
+            .id("42")
+            .retry(Duration::from_secs(3))
+            .data("line one\nline two");
+
+        assert_eq!(
+            event.to_bytes(),
+            ": hello\nevent: update\nid: 42\nretry: 3000\ndata: line one\ndata: line two\n\n"
+        );
+    }
+
+    #[test]
+    fn test_event_strips_newlines_from_id() {
+        let event = Event::default().id("a\nb").data("");
+        assert_eq!(event.to_bytes(), "id: ab\ndata:\n\n");
+    }
+
+    #[test]
+    fn test_empty_comment_is_not_a_blank_line() {
+        assert_eq!(Event::default().comment("").to_bytes(), ":\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_sse_response_with_last_event_id() {
+        let mut app = Foton::new();
+        app.get("/events", |LastEventId(last): LastEventId| async move {
+            Sse::new(move |tx: SseSender| async move {
+                let id = last.unwrap_or_default();
+                tx.send(Event::default().id(id).data("resumed")).await.ok();
+            })
+        });
+
+        let res = app
+            .into_test_client()
+            .get("/events")
+            .header("last-event-id", "7")
+            .send()
+            .await;
+
+        assert_eq!(res.headers()["content-type"], "text/event-stream");
+        assert_eq!(res.into_text().await.unwrap(), "id: 7\ndata: resumed\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_sse_keep_alive() {
+        let mut app = Foton::new();
+        app.get("/events", |_: Req| async {
+            Sse::new(|tx: SseSender| async move {
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                tx.send_data("done").await.ok();
+            })
+            .keep_alive(Duration::from_millis(20))
+            .keep_alive_text("ping")
+        });
+
+        let res = app.into_test_client().get("/events").send().await;
+        let body = res.into_text().await.unwrap();
+        assert!(body.starts_with(": ping\n\n"));
+        assert!(body.ends_with("data: done\n\n"));
+    }
+}
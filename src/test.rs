@@ -0,0 +1,277 @@
+//! In-process test client.
+//!
+//! Drives an application through the same routing and middleware pipeline as
+//! [`Foton::listen`], without binding a socket.
+//!
+//! ```rust
+//! use foton::{Foton, Req, Res};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut app = Foton::new();
+//! app.get("/", |_: Req| async { Res::text("Hello") });
+//!
+//! let client = app.into_test_client();
+//! let res = client.get("/").send().await;
+//! assert_eq!(res.status_code(), 200);
+//! assert_eq!(res.into_text().await.unwrap(), "Hello");
+//! # }
+//! ```
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Method, Request, header};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::connect_info::ConnectInfo;
+use crate::{Foton, Res};
+
+/// Client that dispatches requests to an application in-process.
+pub struct TestClient<S = ()> {
+    app: Arc<Foton<S>>,
+}
+
+impl<S: Send + Sync + 'static> TestClient<S> {
+    /// Build the application's router and wrap it in a client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the routes fail to [`build`](Foton::build).
+    pub fn new(mut app: Foton<S>) -> Self {
+        if let Err(error) = app.build() {
+            panic!("{}", error);
+        }
+        Self { app: Arc::new(app) }
+    }
+
+    /// Start a request with the given method and URI.
+    pub fn request(&self, method: Method, uri: impl Into<String>) -> TestRequest<S> {
+        TestRequest {
+            app: Arc::clone(&self.app),
+            method,
+            uri: uri.into(),
+            headers: header::HeaderMap::new(),
+            body: Bytes::new(),
+            connect_info: None,
+        }
+    }
+
+    /// Start a GET request.
+    pub fn get(&self, uri: impl Into<String>) -> TestRequest<S> {
+        self.request(Method::GET, uri)
+    }
+
+    /// Start a POST request.
+    pub fn post(&self, uri: impl Into<String>) -> TestRequest<S> {
+        self.request(Method::POST, uri)
+    }
+
+    /// Start a PUT request.
+    pub fn put(&self, uri: impl Into<String>) -> TestRequest<S> {
+        self.request(Method::PUT, uri)
+    }
+
+    /// Start a DELETE request.
+    pub fn delete(&self, uri: impl Into<String>) -> TestRequest<S> {
+        self.request(Method::DELETE, uri)
+    }
+
+    /// Start a PATCH request.
+    pub fn patch(&self, uri: impl Into<String>) -> TestRequest<S> {
+        self.request(Method::PATCH, uri)
+    }
+}
+
+/// Request builder returned by [`TestClient`].
+pub struct TestRequest<S = ()> {
+    app: Arc<Foton<S>>,
+    method: Method,
+    uri: String,
+    headers: header::HeaderMap,
+    body: Bytes,
+    connect_info: Option<ConnectInfo>,
+}
+
+impl<S: Send + Sync + 'static> TestRequest<S> {
+    /// Add header.
+    pub fn header(mut self, name: impl AsRef<str>, value: impl AsRef<str>) -> Self {
+        if let (Ok(name), Ok(value)) = (
+            header::HeaderName::from_bytes(name.as_ref().as_bytes()),
+            header::HeaderValue::from_str(value.as_ref()),
+        ) {
+            self.headers.append(name, value);
+        }
+        self
+    }
+
+    /// Set raw body.
+    pub fn body(mut self, bytes: impl Into<Bytes>) -> Self {
+        self.body = bytes.into();
+        self
+    }
+
+    /// Set text body with `text/plain` content type.
+    pub fn text(self, body: impl Into<String>) -> Self {
+        self.header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(body.into())
+    }
+
+    /// Set JSON body with `application/json` content type.
+    pub fn json<T: Serialize>(self, value: &T) -> Self {
+        let bytes = serde_json::to_vec(value).expect("failed to serialize JSON test body");
+        self.header(header::CONTENT_TYPE, "application/json")
+            .body(bytes)
+    }
+
+    /// Set urlencoded form body.
+    pub fn form<T: Serialize>(self, value: &T) -> Self {
+        let body = serde_urlencoded::to_string(value).expect("failed to serialize form test body");
+        self.header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    /// Simulate a direct connection from `addr`, to the unspecified local
+    /// address `0.0.0.0:0`.
+    pub fn remote_addr(mut self, addr: impl Into<SocketAddr>) -> Self {
+        let local_addr = SocketAddr::from(([0, 0, 0, 0], 0));
+        self.connect_info = Some(ConnectInfo::new(addr.into(), local_addr));
+        self
+    }
+
+    /// Simulate a connection with the given addresses, such as one received
+    /// through the PROXY protocol.
+    pub fn connect_info(mut self, info: ConnectInfo) -> Self {
+        self.connect_info = Some(info);
+        self
+    }
+
+    /// Dispatch the request and return the response.
+    pub async fn send(self) -> Res {
+        let mut req = Request::builder()
+            .method(self.method)
+            .uri(self.uri)
+            .body(Full::new(self.body))
+            .expect("invalid test request");
+        *req.headers_mut() = self.headers;
+
+        let response = match self.app.handle_request(req, self.connect_info).await {
+            Ok(response) => response,
+            Err(e) => match e {},
+        };
+        Res::from_hyper(response)
+    }
+}
+
+/// Unique path under the system temp directory, removed with everything
+/// below it when dropped.
+#[cfg(test)]
+pub(crate) struct TempPath(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempPath {
+    /// The file name ends with `name`, so extensions are kept.
+    pub(crate) fn new(name: &str) -> Self {
+        let name = format!("foton-{}-{}", uuid::Uuid::new_v4(), name);
+        Self(std::env::temp_dir().join(name))
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempPath {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempPath {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.0.is_dir() {
+            let _ = std::fs::remove_dir_all(&self.0);
+        } else {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractors::{Json, Path};
+    use crate::{Next, Req, from_fn};
+
+    #[tokio::test]
+    async fn test_get_text() {
+        let mut app = Foton::new();
+        app.get("/", |_: Req| async { "Hello" });
+
+        let res = app.into_test_client().get("/").send().await;
+        assert_eq!(res.status_code(), 200);
+        assert_eq!(res.into_text().await.unwrap(), "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_json_roundtrip_with_path() {
+        #[derive(serde::Deserialize, serde::Serialize)]
+        struct Item {
+            name: String,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Params {
+            id: String,
+        }
+
+        let mut app = Foton::new();
+        app.post(
+            "/items/{id}",
+            |Path(params): Path<Params>, Json(item): Json<Item>| async move {
+                Res::json(&serde_json::json!({ "id": params.id, "name": item.name }))
+            },
+        );
+
+        let client = app.into_test_client();
+        let res = client
+            .post("/items/7")
+            .json(&Item {
+                name: "widget".into(),
+            })
+            .send()
+            .await;
+
+        assert_eq!(res.status_code(), 200);
+        let body: serde_json::Value = res.into_json().await.unwrap();
+        assert_eq!(body["id"], "7");
+        assert_eq!(body["name"], "widget");
+    }
+
+    #[tokio::test]
+    async fn test_middleware_and_not_found() {
+        let mut app = Foton::new();
+        app.attach(from_fn(|req: Req, _state, next: Next| async move {
+            next.run(req).await.header("x-test", "1")
+        }));
+        app.get("/", |_: Req| async { "ok" });
+
+        let client = app.into_test_client();
+        let res = client.get("/").send().await;
+        assert_eq!(res.headers().get("x-test").unwrap(), "1");
+
+        let res = client.get("/missing").send().await;
+        assert_eq!(res.status_code(), 404);
+
+        let res = client.delete("/").send().await;
+        assert_eq!(res.status_code(), 405);
+    }
+}
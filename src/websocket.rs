@@ -5,7 +5,7 @@
 //! ## Usage
 //!
 //! ```rust,no_run
-//! use rust_api::{Res, WebSocketUpgrade, WebSocket, Message};
+//! use foton::{Res, WebSocketUpgrade, WebSocket, Message};
 //!
 //! async fn handle_ws(mut ws: WebSocket) {
 //!     while let Ok(Some(msg)) = ws.receive().await {